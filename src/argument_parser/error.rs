@@ -3,6 +3,11 @@ use std::fmt;
 #[derive(Debug)]
 pub enum Error {
     YotTypeInvalid,
+    MapFormatUnknown,
+    RecordLengthOutOfRange,
+    BankSizeInvalid,
+    DeviceInvalid,
+    InterruptInvalid,
 }
 
 impl fmt::Display for Error {
@@ -11,6 +16,21 @@ impl fmt::Display for Error {
             Error::YotTypeInvalid => write!(f,
                 "invalid Yot type",
             ),
+            Error::MapFormatUnknown => write!(f,
+                "unknown label map format",
+            ),
+            Error::RecordLengthOutOfRange => write!(f,
+                "record length must be between 1 and 255",
+            ),
+            Error::BankSizeInvalid => write!(f,
+                "bank size must be a positive number of bytes",
+            ),
+            Error::DeviceInvalid => write!(f,
+                "device must be `console-out`, `console-in` or `cycles` followed by `@` and an address",
+            ),
+            Error::InterruptInvalid => write!(f,
+                "interrupt must be a number of instructions, `every:` followed by a positive number of instructions, or `device`",
+            ),
         }
     }
 }
@@ -1,10 +1,13 @@
 pub use error::Error;
 use structopt::StructOpt;
+use structopt::clap::{self, ErrorKind};
+use std::ffi::OsString;
+use std::fmt;
 use std::path::PathBuf;
 
 mod error;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum YotType {
     Y8 = 1,
     Y16 = 2,
@@ -12,15 +15,110 @@ pub enum YotType {
     Y64 = 8,
 }
 
+impl fmt::Display for YotType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            YotType::Y8 => write!(f, "yot-8"),
+            YotType::Y16 => write!(f, "yot-16"),
+            YotType::Y32 => write!(f, "yot-32"),
+            YotType::Y64 => write!(f, "yot-64"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MapFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DeviceKind {
+    ConsoleOutput,
+    ConsoleInput,
+    CycleCounter,
+}
+
+/// A device to map into the address space of the emulator, given as
+/// `<kind>@<address>`.
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceConfig {
+    pub kind: DeviceKind,
+    pub address: u64,
+}
+
+/// What raises interrupts in the emulator.
+#[derive(Debug, Clone, Copy)]
+pub enum InterruptConfig {
+    /// After the given number of instructions, given as the number.
+    At(u64),
+    /// After every given number of instructions, given as `every:<number>`.
+    Every(u64),
+    /// Whenever a device asks for one, given as `device`.
+    Device,
+}
+
 #[derive(Debug, StructOpt)]
-pub struct Config {
+#[allow(clippy::large_enum_variant)]
+pub enum Config {
+    /// Assemble a Yot Assembly source file into a binary
+    Assemble(AssembleConfig),
+    /// Disassemble a Yot binary into Yot Assembly
+    Disassemble(DisassembleConfig),
+    /// Run a Yot binary until it reaches `brk`, then print the stack and memory
+    Run(RunConfig),
+    /// Debug a Yot Assembly program interactively
+    Debug(DebugConfig),
+    /// Run a Yot Assembly program and check its `;=` stack assertion comments
+    Check(CheckConfig),
+    /// Run the `;test` cases of a Yot Assembly program
+    Test(TestConfig),
+    /// Run a Yot Assembly program and report where it spends its instructions
+    Profile(ProfileConfig),
+    /// Serve a Yot binary to GDB over the remote serial protocol
+    ///
+    /// Works with clients that take the register layout from the target
+    /// description, like scripts and front ends speaking the protocol. It does
+    /// not work with a stock GDB: GDB has no Yot architecture, so it applies
+    /// the register layout of its default one and rejects the replies.
+    Gdb(GdbConfig),
+}
+
+/// The first arguments that name a subcommand or ask for the help or version,
+/// so that the arguments after them are never taken as those of `assemble`.
+const SUBCOMMANDS_AND_FLAGS: [&str; 13] = [
+    "assemble", "disassemble", "run", "debug", "check", "test", "profile", "gdb", "help",
+    "-h", "--help", "-V", "--version",
+];
+
+impl Config {
+    /// Parses the command line. Without a subcommand the arguments are taken
+    /// as those of `assemble`, as they were before there were subcommands.
+    pub fn from_command_line() -> Config {
+        Config::parse(std::env::args_os().collect()).unwrap_or_else(|err| err.exit())
+    }
+
+    fn parse(mut args: Vec<OsString>) -> Result<Config, clap::Error> {
+        let selects_subcommand = args.get(1).is_some_and(|arg| SUBCOMMANDS_AND_FLAGS.iter().any(|name| arg == name));
+        match Config::from_iter_safe(&args) {
+            Err(err) if !selects_subcommand && matches!(err.kind, ErrorKind::UnknownArgument | ErrorKind::UnrecognizedSubcommand) => {
+                args.insert(1.min(args.len()), OsString::from("assemble"));
+                Config::from_iter_safe(&args)
+            }
+            result => result,
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct AssembleConfig {
     /// Yot type
     #[structopt(name = "YOT TYPE", parse(try_from_str = parse_yot_type))]
     pub yot_type: YotType,
-    /// Yot Assembly source file path
+    /// Yot Assembly source file path, or `-` for the standard input
     #[structopt(name = "SOURCE FILE", parse(from_os_str))]
     pub source_path: PathBuf,
-    /// Output binary file path
+    /// Output binary file path, or `-` for the standard output
     #[structopt(name = "OUTPUT FILE", parse(from_os_str))]
     pub output_path: PathBuf,
     /// Initial data stack pointer
@@ -29,9 +127,193 @@ pub struct Config {
     /// Binary size
     #[structopt(short = "s", long = "exact-size")]
     pub exact_binary_size: Option<usize>,
+    /// Output format: `raw`, `ihex`, `srec`, `c`, `rust` or any other registered format
+    #[structopt(short = "f", long = "format", default_value = "raw")]
+    pub output_format: String,
+    /// Maximum number of data bytes per record in record-based output formats
+    #[structopt(long = "record-length", default_value = "16", parse(try_from_str = parse_record_length))]
+    pub record_length: usize,
+    /// Name of the array in source output formats, the output file name by default
+    #[structopt(long = "array-name")]
+    pub array_name: Option<String>,
+    /// Split the raw binary into banks of the given size, written as separate files
+    #[structopt(long = "bank-size", conflicts_with = "output-format", parse(try_from_str = parse_bank_size))]
+    pub bank_size: Option<usize>,
+    /// Bank file name template, where `{name}` is the output file stem, `{ext}` its extension and `{bank}` the bank index
+    #[structopt(long = "bank-name", default_value = "{name}.{bank}{ext}")]
+    pub bank_name_template: String,
+    /// Byte to fill up the final bank with
+    #[structopt(long = "fill-byte", default_value = "255")]
+    pub fill_byte: u8,
+    /// Label not allowed to cross a bank boundary, e.g. a routine that has to run from a single bank
+    #[structopt(long = "deny-bank-crossing", number_of_values = 1, requires = "bank-size")]
+    pub pinned_labels: Vec<String>,
+    /// Write an assembly listing to the given path
+    #[structopt(long = "listing", parse(from_os_str))]
+    pub listing_path: Option<PathBuf>,
+    /// Write a map of all labels to the given path
+    #[structopt(long = "map", parse(from_os_str))]
+    pub map_path: Option<PathBuf>,
+    /// Label map format: `text` or `json`
+    #[structopt(long = "map-format", default_value = "text", parse(try_from_str = parse_map_format))]
+    pub map_format: MapFormat,
+    /// Write debug info mapping the bytes of the binary to source locations
+    #[structopt(long = "debug-info", parse(from_os_str))]
+    pub debug_info_path: Option<PathBuf>,
+    /// Apply peephole optimizations
+    #[structopt(long = "peephole")]
+    pub peephole: bool,
+    /// Evaluate primitive instructions over adjacent data literals at assembly time
+    #[structopt(long = "fold-constants")]
+    pub fold_constants: bool,
+    /// Turn subroutine jumps followed by a return into plain jumps to callees that take no stack arguments
+    #[structopt(long = "tail-calls")]
+    pub tail_calls: bool,
+    /// Remove label-delimited blocks unreachable from the entry point
+    #[structopt(long = "strip-dead-code")]
+    pub strip_dead_code: bool,
+    /// Label to keep when stripping dead code, e.g. one only reached indirectly
+    #[structopt(long = "keep", number_of_values = 1)]
+    pub kept_labels: Vec<String>,
+    /// Write binary output to the standard output even if it is a terminal
+    #[structopt(long = "force")]
+    pub force: bool,
+    /// Print additional information about the assembly
+    #[structopt(short = "v", long = "verbose")]
+    pub verbose: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct DisassembleConfig {
+    /// Yot type
+    #[structopt(name = "YOT TYPE", parse(try_from_str = parse_yot_type))]
+    pub yot_type: YotType,
+    /// Yot binary file path, or `-` for the standard input
+    #[structopt(name = "BINARY FILE", parse(from_os_str))]
+    pub binary_path: PathBuf,
+    /// Output source file path, or `-` for the standard output
+    #[structopt(name = "OUTPUT FILE", parse(from_os_str))]
+    pub output_path: PathBuf,
+    /// Take label names from a label map in the text format written by `assemble --map`
+    #[structopt(long = "map", parse(from_os_str))]
+    pub map_path: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct RunConfig {
+    /// Yot type
+    #[structopt(name = "YOT TYPE", parse(try_from_str = parse_yot_type))]
+    pub yot_type: YotType,
+    /// Yot binary file path, or `-` for the standard input
+    #[structopt(name = "BINARY FILE", parse(from_os_str))]
+    pub binary_path: PathBuf,
+    /// Stop after executing the given number of instructions
+    #[structopt(long = "max-steps")]
+    pub max_steps: Option<u64>,
+    /// Map a device at an address, e.g. `console-out@0xff00`: `console-out`, `console-in` or `cycles`
+    #[structopt(long = "device", number_of_values = 1, parse(try_from_str = parse_device))]
+    pub devices: Vec<DeviceConfig>,
+    /// File path the console input device reads from, the standard input by default
+    #[structopt(long = "console-input", parse(from_os_str))]
+    pub console_input_path: Option<PathBuf>,
+    /// File path the console output device writes to, the standard output by default
+    #[structopt(long = "console-output", parse(from_os_str))]
+    pub console_output_path: Option<PathBuf>,
+    /// Raise an interrupt after a number of instructions, after `every:<number>` of them, or on `device` requests
+    #[structopt(long = "interrupt", number_of_values = 1, parse(try_from_str = parse_interrupt))]
+    pub interrupts: Vec<InterruptConfig>,
+    /// Report when interrupt handlers are entered and when they return
+    #[structopt(long = "trace-interrupts")]
+    pub trace_interrupts: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct DebugConfig {
+    /// Yot type
+    #[structopt(name = "YOT TYPE", parse(try_from_str = parse_yot_type))]
+    pub yot_type: YotType,
+    /// Yot Assembly source file path
+    #[structopt(name = "SOURCE FILE", parse(from_os_str))]
+    pub source_path: PathBuf,
+    /// Initial data stack pointer
+    #[structopt(long = "sp")]
+    pub initial_stack_pointer: u64,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct CheckConfig {
+    /// Yot type
+    #[structopt(name = "YOT TYPE", parse(try_from_str = parse_yot_type))]
+    pub yot_type: YotType,
+    /// Yot Assembly source file path, or `-` for the standard input
+    #[structopt(name = "SOURCE FILE", parse(from_os_str))]
+    pub source_path: PathBuf,
+    /// Initial data stack pointer
+    #[structopt(long = "sp")]
+    pub initial_stack_pointer: u64,
+    /// Stop after executing the given number of instructions
+    #[structopt(long = "max-steps", default_value = "1000000")]
+    pub max_steps: u64,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct TestConfig {
+    /// Yot type
+    #[structopt(name = "YOT TYPE", parse(try_from_str = parse_yot_type))]
+    pub yot_type: YotType,
+    /// Yot Assembly source file path, or `-` for the standard input
+    #[structopt(name = "SOURCE FILE", parse(from_os_str))]
+    pub source_path: PathBuf,
+    /// Initial data stack pointer
+    #[structopt(long = "sp")]
+    pub initial_stack_pointer: u64,
+    /// Fail each test case after executing the given number of instructions
+    #[structopt(long = "max-steps", default_value = "100000")]
+    pub max_steps: u64,
+    /// Write the source annotated with how often the test cases executed each line to the given path
+    #[structopt(long = "coverage", parse(from_os_str))]
+    pub coverage_path: Option<PathBuf>,
+    /// Write the coverage of the test cases as an LCOV tracefile to the given path
+    #[structopt(long = "lcov", parse(from_os_str))]
+    pub lcov_path: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ProfileConfig {
+    /// Yot type
+    #[structopt(name = "YOT TYPE", parse(try_from_str = parse_yot_type))]
+    pub yot_type: YotType,
+    /// Yot Assembly source file path, or `-` for the standard input
+    #[structopt(name = "SOURCE FILE", parse(from_os_str))]
+    pub source_path: PathBuf,
+    /// Initial data stack pointer
+    #[structopt(long = "sp")]
+    pub initial_stack_pointer: u64,
+    /// Stop after executing the given number of instructions
+    #[structopt(long = "max-steps")]
+    pub max_steps: Option<u64>,
+    /// Cost table file path, with a mnemonic or `0x`-prefixed opcode and its cost per line
+    #[structopt(long = "costs", parse(from_os_str))]
+    pub costs_path: Option<PathBuf>,
+    /// Output file path for the stacks in the folded format used by flame graph tools
+    #[structopt(long = "folded", parse(from_os_str))]
+    pub folded_path: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct GdbConfig {
+    /// Yot type
+    #[structopt(name = "YOT TYPE", parse(try_from_str = parse_yot_type))]
+    pub yot_type: YotType,
+    /// Yot binary file path
+    #[structopt(name = "BINARY FILE", parse(from_os_str))]
+    pub binary_path: PathBuf,
+    /// Listen for GDB on the given local TCP port instead of talking to it over the standard input and output
+    #[structopt(long = "port")]
+    pub port: Option<u16>,
 }
 
-fn parse_yot_type(input: &str) -> Result<YotType, Error> {
+pub fn parse_yot_type(input: &str) -> Result<YotType, Error> {
     match input {
         "yot-8" => Ok(YotType::Y8),
         "yot-16" => Ok(YotType::Y16),
@@ -40,3 +322,114 @@ fn parse_yot_type(input: &str) -> Result<YotType, Error> {
         _ => Err(Error::YotTypeInvalid),
     }
 }
+
+fn parse_map_format(input: &str) -> Result<MapFormat, Error> {
+    match input {
+        "text" => Ok(MapFormat::Text),
+        "json" => Ok(MapFormat::Json),
+        _ => Err(Error::MapFormatUnknown),
+    }
+}
+
+fn parse_record_length(input: &str) -> Result<usize, Error> {
+    match input.parse::<usize>() {
+        Ok(record_length) if (1..=255).contains(&record_length) => Ok(record_length),
+        _ => Err(Error::RecordLengthOutOfRange),
+    }
+}
+
+fn parse_bank_size(input: &str) -> Result<usize, Error> {
+    match input.parse::<usize>() {
+        Ok(bank_size) if bank_size > 0 => Ok(bank_size),
+        _ => Err(Error::BankSizeInvalid),
+    }
+}
+
+fn parse_device(input: &str) -> Result<DeviceConfig, Error> {
+    let (kind, address) = input.split_once('@').ok_or(Error::DeviceInvalid)?;
+    let kind = match kind {
+        "console-out" => DeviceKind::ConsoleOutput,
+        "console-in" => DeviceKind::ConsoleInput,
+        "cycles" => DeviceKind::CycleCounter,
+        _ => return Err(Error::DeviceInvalid),
+    };
+    let address = match address.strip_prefix("0x") {
+        Some(address) => u64::from_str_radix(address, 16),
+        None => address.parse::<u64>(),
+    }.map_err(|_| Error::DeviceInvalid)?;
+    Ok(DeviceConfig { kind, address })
+}
+
+fn parse_interrupt(input: &str) -> Result<InterruptConfig, Error> {
+    match input.strip_prefix("every:") {
+        _ if input == "device" => Ok(InterruptConfig::Device),
+        Some(period) => match period.parse::<u64>() {
+            Ok(period) if period > 0 => Ok(InterruptConfig::Every(period)),
+            _ => Err(Error::InterruptInvalid),
+        },
+        None => input.parse::<u64>().map(InterruptConfig::At).map_err(|_| Error::InterruptInvalid),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Config, clap::Error> {
+        Config::parse(args.split_whitespace().map(OsString::from).collect())
+    }
+
+    #[test]
+    fn assembles_without_a_subcommand() {
+        for args in ["ysm yot-8 in.ysm out.bin --sp 80", "ysm --sp 80 yot-8 in.ysm out.bin", "ysm assemble yot-8 in.ysm out.bin --sp 80"] {
+            match parse(args) {
+                Ok(Config::Assemble(config)) => {
+                    assert_eq!(config.yot_type, YotType::Y8);
+                    assert_eq!(config.source_path, PathBuf::from("in.ysm"));
+                    assert_eq!(config.output_path, PathBuf::from("out.bin"));
+                }
+                result => panic!("{}: {:?}", args, result),
+            }
+        }
+    }
+
+    #[test]
+    fn reads_subcommands() {
+        assert!(matches!(parse("ysm run yot-16 out.bin"), Ok(Config::Run(_))));
+        assert!(matches!(parse("ysm disassemble yot-16 out.bin out.ysm"), Ok(Config::Disassemble(_))));
+        assert_eq!(parse("ysm yot-8 in.ysm out.bin").err().map(|err| err.kind), Some(ErrorKind::MissingRequiredArgument));
+    }
+
+    #[test]
+    fn reports_bad_arguments_of_the_subcommand_given() {
+        for (args, usage) in [("ysm run --bogus x", "ysm run"), ("ysm profile -t 8 yot-8 in.ysm --sp 80", "ysm profile")] {
+            match parse(args) {
+                Err(err) => {
+                    assert_eq!(err.kind, ErrorKind::UnknownArgument, "{}", args);
+                    assert!(err.message.contains(usage) && !err.message.contains("ysm assemble"), "{}: {}", args, err.message);
+                }
+                result => panic!("{}: {:?}", args, result),
+            }
+        }
+    }
+
+    #[test]
+    fn reports_bad_arguments_of_assemble_without_a_subcommand() {
+        match parse("ysm yot-8 in.ysm out.bin --sp 80 --bogus") {
+            Err(err) => assert!(err.message.contains("ysm assemble"), "{}", err.message),
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn denies_bank_crossings_only_with_banks() {
+        assert!(matches!(
+            parse("ysm assemble yot-8 in.ysm out.bin --sp 80 --bank-size 16 --deny-bank-crossing mul"),
+            Ok(Config::Assemble(config)) if config.pinned_labels == ["mul"],
+        ));
+        assert_eq!(
+            parse("ysm assemble yot-8 in.ysm out.bin --sp 80 --deny-bank-crossing mul").err().map(|err| err.kind),
+            Some(ErrorKind::MissingRequiredArgument),
+        );
+    }
+}
@@ -0,0 +1,327 @@
+use codespan_reporting::files::{Files, SimpleFiles};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use super::emitter::Assembly;
+use super::emulator::{self, Emulator, Step};
+use super::{InstructionKind, PUSH_OPCODE, Span, get_opcode};
+
+/// How often a `bnc` jumped and how often it fell through.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Branch {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// What a single run of a program executed, by address.
+#[derive(Default)]
+pub struct Trace {
+    /// How often each byte was executed, as an opcode or as the operand of a
+    /// push.
+    executed: BTreeMap<u64, u64>,
+    branches: BTreeMap<u64, Branch>,
+}
+
+impl Trace {
+    pub fn new() -> Trace {
+        Trace::default()
+    }
+
+    /// Executes a single instruction and records it.
+    pub fn step(&mut self, emulator: &mut Emulator) -> Result<Step, emulator::Error> {
+        let address = emulator.program_counter;
+        let opcode = emulator.memory.read(address);
+        // the condition is right below the target address on the stack
+        let address_size = emulator.yot_type as u64;
+        let condition = (opcode == get_opcode(&InstructionKind::Branch) && emulator.depth() > address_size)
+            .then(|| emulator.memory.read(emulator.memory.wrap(emulator.stack_pointer.wrapping_sub(address_size + 1))));
+
+        let step = emulator.step()?;
+        *self.executed.entry(address).or_default() += 1;
+        if opcode == PUSH_OPCODE {
+            *self.executed.entry(emulator.memory.wrap(address.wrapping_add(1))).or_default() += 1;
+        }
+        if let Some(condition) = condition {
+            let branch = self.branches.entry(address).or_default();
+            if condition != 0 {
+                branch.taken += 1;
+            } else {
+                branch.not_taken += 1;
+            }
+        }
+        Ok(step)
+    }
+
+    /// Runs the program until it reaches `brk`, or fails after executing the
+    /// given number of instructions, recording everything it executes.
+    pub fn run(&mut self, emulator: &mut Emulator, max_steps: Option<u64>) -> Result<(), emulator::Error> {
+        emulator.run_with(max_steps, |emulator| self.step(emulator))
+    }
+}
+
+struct Counts {
+    span: Span,
+    hits: u64,
+    branch: Option<Branch>,
+}
+
+/// The execution counts of every line of code and the outcomes of every
+/// `bnc` on it.
+#[derive(Default)]
+struct Line {
+    hits: u64,
+    branches: Vec<Branch>,
+}
+
+/// What any number of runs executed, by source span, so that runs of
+/// different binaries assembled from the same source can be combined.
+#[derive(Default)]
+pub struct Coverage {
+    spans: BTreeMap<(usize, usize), Counts>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage::default()
+    }
+
+    /// Adds a run of the assembly, mapping what it executed back to the
+    /// source through the fragments it was assembled from. Fragments without
+    /// a span, like the header, are left out.
+    pub fn add(&mut self, assembly: &Assembly, trace: &Trace) {
+        let branch_opcode = get_opcode(&InstructionKind::Branch);
+        for fragment in assembly.fragments.iter().filter(|fragment| fragment.size > 0) {
+            let span = match fragment.span {
+                Some(span) => span,
+                None => continue,
+            };
+            let range = fragment.address as u64..(fragment.address + fragment.size) as u64;
+            let hits = trace.executed.range(range).map(|(_, hits)| *hits).max().unwrap_or(0);
+            // a `bnc` is the only single byte fragment with its opcode, data
+            // literals and addresses being pushes
+            let is_branch = fragment.size == 1 && assembly.binary[fragment.address] == branch_opcode;
+
+            let counts = self.spans.entry((span.file_id, span.from.offset)).or_insert(Counts {
+                span,
+                hits: 0,
+                branch: is_branch.then(Branch::default),
+            });
+            counts.hits += hits;
+            if let (Some(branch), Some(traced)) = (&mut counts.branch, trace.branches.get(&(fragment.address as u64))) {
+                branch.taken += traced.taken;
+                branch.not_taken += traced.not_taken;
+            }
+        }
+    }
+
+    /// The lines of code of every file by their index, each executed as
+    /// often as its most executed span.
+    fn lines(&self, files: &SimpleFiles<String, &str>) -> BTreeMap<usize, BTreeMap<usize, Line>> {
+        let mut lines: BTreeMap<usize, BTreeMap<usize, Line>> = BTreeMap::new();
+        for counts in self.spans.values() {
+            let file_id = counts.span.file_id;
+            let line_index = match files.line_index(file_id, counts.span.from.offset) {
+                Ok(line_index) => line_index,
+                Err(_) => continue,
+            };
+            let line = lines.entry(file_id).or_default().entry(line_index).or_default();
+            line.hits = line.hits.max(counts.hits);
+            line.branches.extend(counts.branch);
+        }
+        lines
+    }
+
+    /// Renders the source file with the number of times each line of code
+    /// was executed in front of it, `#####` for lines that never were and
+    /// `-` for lines without code. Every `bnc` that always or never jumped is
+    /// marked below its line and listed again at the end.
+    pub fn listing(&self, files: &SimpleFiles<String, &str>, file_id: usize) -> String {
+        let lines = self.lines(files).remove(&file_id).unwrap_or_default();
+        let source: &str = files.source(file_id).unwrap_or("");
+        let line_count = files.line_index(file_id, source.len()).map(|index| index + 1).unwrap_or(0);
+        let mut output: String = String::new();
+        let mut partial: Vec<(usize, &'static str)> = Vec::new();
+
+        for line_index in 0..line_count {
+            let text = files.line_range(file_id, line_index)
+                .map(|range| source[range].trim_end_matches(&['\r', '\n'][..]))
+                .unwrap_or("");
+            let hits = match lines.get(&line_index) {
+                Some(Line { hits: 0, .. }) => "#####".to_owned(),
+                Some(Line { hits, .. }) => hits.to_string(),
+                None => "-".to_owned(),
+            };
+            let _ = writeln!(output, "{:>9}:{:>5}:{}", hits, line_index + 1, text);
+
+            for branch in lines.get(&line_index).map(|line| line.branches.as_slice()).unwrap_or_default() {
+                let _ = writeln!(output, "{:>9}:{:>5}: branch taken {}, not taken {}", "", "", branch.taken, branch.not_taken);
+                let never = match (branch.taken, branch.not_taken) {
+                    (0, 0) => "never executed",
+                    (0, _) => "never taken",
+                    (_, 0) => "always taken",
+                    _ => continue,
+                };
+                let _ = writeln!(output, "{:>9}:{:>5}: ^^^^^^ branch {}", ">>>>>", "", never);
+                partial.push((line_index + 1, never));
+            }
+        }
+
+        let covered = lines.values().filter(|line| line.hits > 0).count();
+        let branches = lines.values().map(|line| line.branches.len()).sum::<usize>();
+        let _ = writeln!(output);
+        let _ = writeln!(output, "lines executed: {} of {}", covered, lines.len());
+        let _ = writeln!(output, "branches taken both ways: {} of {}", branches - partial.len(), branches);
+        for (line_number, never) in partial.iter() {
+            let _ = writeln!(output, "  line {}: branch {}", line_number, never);
+        }
+        output
+    }
+
+    /// Renders the coverage of every file in the LCOV tracefile format. Each
+    /// `bnc` is a block of two branches, the jump and the fall-through.
+    pub fn lcov(&self, files: &SimpleFiles<String, &str>) -> String {
+        let mut output: String = String::new();
+        for (file_id, lines) in self.lines(files).iter() {
+            let _ = writeln!(output, "TN:");
+            let _ = writeln!(output, "SF:{}", files.name(*file_id).unwrap_or_default());
+            let mut branch_count: usize = 0;
+            let mut branches_hit: usize = 0;
+            for (line_index, line) in lines.iter() {
+                for (block, branch) in line.branches.iter().enumerate() {
+                    for (i, taken) in [branch.taken, branch.not_taken].iter().enumerate() {
+                        let taken = if line.hits == 0 { "-".to_owned() } else { taken.to_string() };
+                        let _ = writeln!(output, "BRDA:{},{},{},{}", line_index + 1, block, i, taken);
+                    }
+                    branch_count += 2;
+                    branches_hit += (branch.taken > 0) as usize + (branch.not_taken > 0) as usize;
+                }
+            }
+            let _ = writeln!(output, "BRF:{}", branch_count);
+            let _ = writeln!(output, "BRH:{}", branches_hit);
+            for (line_index, line) in lines.iter() {
+                let _ = writeln!(output, "DA:{},{}", line_index + 1, line.hits);
+            }
+            let _ = writeln!(output, "LF:{}", lines.len());
+            let _ = writeln!(output, "LH:{}", lines.values().filter(|line| line.hits > 0).count());
+            let _ = writeln!(output, "end_of_record");
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::YotType;
+    use super::super::emitter::{Optimizations, emit};
+    use super::super::tokenizer::tokenize;
+
+    const BRANCHES: &str = "#00 @fall bnc
+:fall
+#01 @jump bnc
+#05
+:jump
+#02
+:loop
+#01 sub #00 pck @loop bnc
+brk
+";
+
+    fn files() -> SimpleFiles<String, &'static str> {
+        let mut files = SimpleFiles::new();
+        files.add("test.ysm".to_owned(), BRANCHES);
+        files
+    }
+
+    fn assemble() -> Assembly {
+        let tokens = tokenize(BRANCHES, 0).unwrap();
+        emit(&tokens, YotType::Y8, 0x80, None, &Optimizations::none()).unwrap()
+    }
+
+    fn trace(assembly: &Assembly) -> Trace {
+        let mut emulator = Emulator::new(&assembly.binary, YotType::Y8).unwrap();
+        let mut trace = Trace::new();
+        trace.run(&mut emulator, Some(100)).unwrap();
+        trace
+    }
+
+    fn branch_address(assembly: &Assembly, line_index: usize, files: &SimpleFiles<String, &str>) -> u64 {
+        let branch_opcode = get_opcode(&InstructionKind::Branch);
+        assembly.fragments.iter()
+            .find(|fragment| {
+                fragment.size == 1 && assembly.binary[fragment.address] == branch_opcode
+                    && fragment.span.map(|span| files.line_index(0, span.from.offset).unwrap()) == Some(line_index)
+            })
+            .map(|fragment| fragment.address as u64)
+            .unwrap()
+    }
+
+    #[test]
+    fn counts_branches_taken_and_not_taken() {
+        let files = files();
+        let assembly = assemble();
+        let trace = trace(&assembly);
+        let branch = |line_index| trace.branches.get(&branch_address(&assembly, line_index, &files)).copied();
+        assert_eq!(branch(0), Some(Branch { taken: 0, not_taken: 1 }));
+        assert_eq!(branch(2), Some(Branch { taken: 1, not_taken: 0 }));
+        assert_eq!(branch(7), Some(Branch { taken: 1, not_taken: 1 }));
+    }
+
+    #[test]
+    fn merges_line_hits_over_several_runs() {
+        let files = files();
+        let assembly = assemble();
+        let mut coverage = Coverage::new();
+        coverage.add(&assembly, &trace(&assembly));
+        coverage.add(&assembly, &trace(&assembly));
+        let lines = coverage.lines(&files).remove(&0).unwrap();
+        let hits: Vec<(usize, u64)> = lines.iter().map(|(line_index, line)| (*line_index, line.hits)).collect();
+        assert_eq!(hits, [(0, 2), (2, 2), (3, 0), (5, 2), (7, 4), (8, 2)]);
+        assert_eq!(lines[&7].branches, [Branch { taken: 2, not_taken: 2 }]);
+    }
+
+    #[test]
+    fn highlights_branches_that_always_or_never_jump() {
+        let files = files();
+        let assembly = assemble();
+        let mut coverage = Coverage::new();
+        coverage.add(&assembly, &trace(&assembly));
+        let listing = coverage.listing(&files, 0);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines[0], "        1:    1:#00 @fall bnc");
+        assert_eq!(lines[1], "         :     : branch taken 0, not taken 1");
+        assert_eq!(lines[2], "    >>>>>:     : ^^^^^^ branch never taken");
+        assert_eq!(lines[3], "        -:    2::fall");
+        assert!(listing.contains("    #####:    4:#05\n"));
+        assert!(listing.contains("lines executed: 5 of 6\n"));
+        assert!(listing.contains("branches taken both ways: 1 of 3\n"));
+        assert!(listing.ends_with("  line 1: branch never taken\n  line 3: branch always taken\n"));
+    }
+
+    #[test]
+    fn writes_lcov_records() {
+        let files = files();
+        let assembly = assemble();
+        let mut coverage = Coverage::new();
+        coverage.add(&assembly, &trace(&assembly));
+        assert_eq!(coverage.lcov(&files), "TN:
+SF:test.ysm
+BRDA:1,0,0,0
+BRDA:1,0,1,1
+BRDA:3,0,0,1
+BRDA:3,0,1,0
+BRDA:8,0,0,1
+BRDA:8,0,1,1
+BRF:6
+BRH:4
+DA:1,1
+DA:3,1
+DA:4,0
+DA:6,1
+DA:8,2
+DA:9,1
+LF:6
+LH:5
+end_of_record
+");
+    }
+}
@@ -0,0 +1,40 @@
+use std::fmt;
+use crate::reporter::{Diagnostic, Report, Reporter};
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    HeaderMissing,
+    VersionUnsupported { version: String },
+    LineMalformed { line_number: usize },
+    FileUndeclared { file_index: usize, line_number: usize },
+}
+
+impl Report for Error {
+    fn report(&self, r: &Reporter) {
+        r.write(Diagnostic {
+            message: self.to_string(),
+            labels: vec![],
+        });
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Error::HeaderMissing => write!(f,
+                "missing debug info header",
+            ),
+            Error::VersionUnsupported { version } => write!(f,
+                "unsupported debug info version `{}`", version,
+            ),
+            Error::LineMalformed { line_number } => write!(f,
+                "malformed debug info on line {}", line_number,
+            ),
+            Error::FileUndeclared { file_index, line_number } => write!(f,
+                "undeclared file {} referenced on line {} of the debug info", file_index, line_number,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
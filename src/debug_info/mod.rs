@@ -0,0 +1,263 @@
+pub use error::Error;
+use codespan_reporting::files::{Files, SimpleFiles};
+use std::fmt;
+use super::argument_parser::parse_yot_type;
+use super::emitter::Assembly;
+use super::YotType;
+
+mod error;
+
+const VERSION: &str = "1";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Range {
+    pub start: usize,
+    pub end: usize,
+    pub file_index: usize,
+    pub line: usize,
+    pub column: usize,
+    pub label: Option<String>,
+}
+
+/// Maps byte ranges of an assembled binary back to the source locations they
+/// were assembled from.
+///
+/// The debug info is stored as text, one record per line, with fields
+/// separated by single spaces:
+///
+/// ```text
+/// ysm-debug-info 1
+/// yot-type yot-8
+/// file 0 examples/mul.ysm
+/// range 0002 0004 0 2 1 -
+/// range 000a 000d 0 12 3 mul
+/// ```
+///
+/// The first line holds the format version and the second one the Yot type
+/// of the binary. Every `file` record assigns an index to a source file path,
+/// which takes up the rest of the line. Every `range` record holds the
+/// hexadecimal start address and end address (exclusive) of the bytes
+/// assembled from a single token, the index of its file, its 1-based line
+/// and column, and the innermost label preceding it, or `-` if there is
+/// none. Ranges are sorted by their addresses and never overlap. Bytes of the
+/// header and of the padding are not covered by any range. Lines starting
+/// with `;` are comments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DebugInfo {
+    pub yot_type: YotType,
+    pub files: Vec<String>,
+    pub ranges: Vec<Range>,
+}
+
+impl DebugInfo {
+    pub fn new(assembly: &Assembly, yot_type: YotType, files: &SimpleFiles<String, &str>) -> DebugInfo {
+        let mut file_ids: Vec<usize> = Vec::new();
+        let mut ranges: Vec<Range> = Vec::new();
+        let mut label: Option<String> = None;
+
+        for fragment in assembly.fragments.iter() {
+            if fragment.label.is_some() {
+                label = fragment.label.clone();
+            }
+            let span = match fragment.span {
+                Some(span) if fragment.size > 0 => span,
+                _ => continue,
+            };
+            let file_index = match file_ids.iter().position(|file_id| *file_id == span.file_id) {
+                Some(file_index) => file_index,
+                None => {
+                    file_ids.push(span.file_id);
+                    file_ids.len() - 1
+                }
+            };
+            let line_index = files.line_index(span.file_id, span.from.offset).unwrap_or(0);
+            ranges.push(Range {
+                start: fragment.address,
+                end: fragment.address + fragment.size,
+                file_index,
+                line: files.line_number(span.file_id, line_index).unwrap_or(0),
+                column: files.column_number(span.file_id, line_index, span.from.offset).unwrap_or(0),
+                label: label.clone(),
+            });
+        }
+
+        DebugInfo {
+            yot_type,
+            files: file_ids.iter().map(|file_id| files.name(*file_id).unwrap_or_default()).collect(),
+            ranges,
+        }
+    }
+
+    pub fn parse(input: &str) -> Result<DebugInfo, Error> {
+        let mut lines = input
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with(';'));
+
+        match lines.next() {
+            Some((_, line)) if line.starts_with("ysm-debug-info ") => {
+                let version = &line["ysm-debug-info ".len()..];
+                if version != VERSION {
+                    return Err(Error::VersionUnsupported { version: version.to_owned() });
+                }
+            }
+            _ => return Err(Error::HeaderMissing),
+        }
+
+        let yot_type = match lines.next() {
+            Some((line_number, line)) => match line.split_once(' ') {
+                Some(("yot-type", yot_type)) => parse_yot_type(yot_type).map_err(|_| Error::LineMalformed { line_number })?,
+                _ => return Err(Error::LineMalformed { line_number }),
+            },
+            None => return Err(Error::HeaderMissing),
+        };
+
+        let mut files: Vec<String> = Vec::new();
+        let mut ranges: Vec<Range> = Vec::new();
+
+        for (line_number, line) in lines {
+            let malformed = || Error::LineMalformed { line_number };
+            match line.split_once(' ') {
+                Some(("file", rest)) => {
+                    let (file_index, path) = rest.split_once(' ').ok_or_else(malformed)?;
+                    if file_index.parse::<usize>().map_err(|_| malformed())? != files.len() {
+                        return Err(malformed());
+                    }
+                    files.push(path.to_owned());
+                }
+                Some(("range", rest)) => {
+                    let fields: Vec<&str> = rest.split(' ').collect();
+                    if fields.len() != 6 {
+                        return Err(malformed());
+                    }
+                    let range = Range {
+                        start: usize::from_str_radix(fields[0], 16).map_err(|_| malformed())?,
+                        end: usize::from_str_radix(fields[1], 16).map_err(|_| malformed())?,
+                        file_index: fields[2].parse().map_err(|_| malformed())?,
+                        line: fields[3].parse().map_err(|_| malformed())?,
+                        column: fields[4].parse().map_err(|_| malformed())?,
+                        label: match fields[5] {
+                            "-" => None,
+                            label => Some(label.to_owned()),
+                        },
+                    };
+                    if range.file_index >= files.len() {
+                        return Err(Error::FileUndeclared { file_index: range.file_index, line_number });
+                    }
+                    if range.start >= range.end || ranges.last().is_some_and(|last: &Range| last.end > range.start) {
+                        return Err(malformed());
+                    }
+                    ranges.push(range);
+                }
+                _ => return Err(malformed()),
+            }
+        }
+
+        Ok(DebugInfo { yot_type, files, ranges })
+    }
+
+    /// Finds the range covering the byte at the given address.
+    pub fn lookup(&self, address: usize) -> Option<&Range> {
+        let index = self.ranges.partition_point(|range| range.end <= address);
+        self.ranges.get(index).filter(|range| range.start <= address)
+    }
+
+    pub fn file(&self, range: &Range) -> &str {
+        &self.files[range.file_index]
+    }
+}
+
+impl fmt::Display for DebugInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let address_width = self.yot_type as usize * 2;
+        writeln!(f, "ysm-debug-info {}", VERSION)?;
+        writeln!(f, "yot-type {}", self.yot_type)?;
+        for (file_index, file) in self.files.iter().enumerate() {
+            writeln!(f, "file {} {}", file_index, file)?;
+        }
+        for range in self.ranges.iter() {
+            writeln!(
+                f,
+                "range {:0aw$x} {:0aw$x} {} {} {} {}",
+                range.start, range.end, range.file_index, range.line, range.column,
+                range.label.as_deref().unwrap_or("-"),
+                aw = address_width,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::emitter::{Optimizations, emit};
+    use super::super::tokenizer::tokenize;
+
+    const SOURCE: &str = "#01 add\n:start\n  nop\n:loop\n@loop jmp\n";
+
+    fn debug_info(yot_type: YotType) -> DebugInfo {
+        let mut files = SimpleFiles::new();
+        let file_id = files.add("src/with spaces.ysm".to_owned(), SOURCE);
+        let tokens = tokenize(SOURCE, file_id).unwrap();
+        let assembly = emit(&tokens, yot_type, 0x80, None, &Optimizations::none()).unwrap();
+        DebugInfo::new(&assembly, yot_type, &files)
+    }
+
+    #[test]
+    fn renders_a_range_per_token() {
+        assert_eq!(debug_info(YotType::Y16).to_string(), "\
+ysm-debug-info 1
+yot-type yot-16
+file 0 src/with spaces.ysm
+range 0004 0006 0 1 1 -
+range 0006 0007 0 1 5 -
+range 0007 0008 0 3 3 start
+range 0008 000c 0 5 1 loop
+range 000c 000d 0 5 7 loop
+");
+    }
+
+    #[test]
+    fn parses_what_it_renders() {
+        for yot_type in [YotType::Y8, YotType::Y16, YotType::Y32, YotType::Y64] {
+            let debug_info = debug_info(yot_type);
+            assert_eq!(DebugInfo::parse(&debug_info.to_string()), Ok(debug_info));
+        }
+        let commented = format!("; generated\n\n{}", debug_info(YotType::Y8));
+        assert_eq!(DebugInfo::parse(&commented), Ok(debug_info(YotType::Y8)));
+    }
+
+    #[test]
+    fn rejects_malformed_debug_info() {
+        let header = "ysm-debug-info 1\nyot-type yot-8\nfile 0 a.ysm\n";
+        assert_eq!(DebugInfo::parse(""), Err(Error::HeaderMissing));
+        assert_eq!(DebugInfo::parse("ysm-debug-info 2\n"), Err(Error::VersionUnsupported { version: "2".to_owned() }));
+        assert_eq!(DebugInfo::parse("ysm-debug-info 1\nyot-type yot-7\n"), Err(Error::LineMalformed { line_number: 2 }));
+        assert_eq!(DebugInfo::parse(&format!("{}file 2 b.ysm\n", header)), Err(Error::LineMalformed { line_number: 4 }));
+        assert_eq!(DebugInfo::parse(&format!("{}range 02 04 1 1 1 -\n", header)), Err(Error::FileUndeclared { file_index: 1, line_number: 4 }));
+        assert_eq!(DebugInfo::parse(&format!("{}range 02 04 0 1 1\n", header)), Err(Error::LineMalformed { line_number: 4 }));
+        assert_eq!(DebugInfo::parse(&format!("{}range 04 04 0 1 1 -\n", header)), Err(Error::LineMalformed { line_number: 4 }));
+        // overlapping ranges
+        assert_eq!(
+            DebugInfo::parse(&format!("{}range 02 04 0 1 1 -\nrange 03 05 0 1 1 -\n", header)),
+            Err(Error::LineMalformed { line_number: 5 }),
+        );
+    }
+
+    #[test]
+    fn looks_up_the_range_covering_an_address() {
+        let debug_info = debug_info(YotType::Y16);
+        let located = |address| debug_info.lookup(address).map(|range| (range.start, range.line, range.column));
+        // the header
+        assert_eq!(located(0x0003), None);
+        assert_eq!(located(0x0004), Some((0x0004, 1, 1)));
+        assert_eq!(located(0x0005), Some((0x0004, 1, 1)));
+        assert_eq!(located(0x000b), Some((0x0008, 5, 1)));
+        assert_eq!(located(0x000c), Some((0x000c, 5, 7)));
+        assert_eq!(located(0x000d), None);
+        let range = debug_info.lookup(0x0007).unwrap();
+        assert_eq!((debug_info.file(range), range.label.as_deref()), ("src/with spaces.ysm", Some("start")));
+    }
+}
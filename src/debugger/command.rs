@@ -0,0 +1,129 @@
+use super::Error;
+
+pub const HELP: &str = "\
+b, break <label|line>      stop before executing the label or source line
+cl, clear <label|line>     remove a breakpoint
+w, watch <address>         stop after the byte at the address changes
+u, unwatch <address>       remove a watchpoint
+s, step [count]            execute one or more instructions
+n, next                    execute one instruction, stepping over subroutine jumps
+c, continue                run until a breakpoint, a watchpoint or `brk`
+st, stack                  print the data stack from the bottom up
+m, memory <address> [n]    print n bytes of memory, 16 by default
+wh, where                  show the next instruction in the source
+h, help                    print this help
+q, quit                    leave the debugger";
+
+/// A breakpoint location as given on the command line.
+#[derive(Debug, PartialEq)]
+pub enum Location {
+    Label(String),
+    Line(usize),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Break(Location),
+    Clear(Location),
+    Watch(u64),
+    Unwatch(u64),
+    Step(usize),
+    Next,
+    Continue,
+    Stack,
+    Memory { address: u64, length: usize },
+    Where,
+    Help,
+    Quit,
+}
+
+fn location(argument: Option<&str>) -> Result<Location, Error> {
+    match argument {
+        Some(argument) => match argument.parse::<usize>() {
+            Ok(line) => Ok(Location::Line(line)),
+            Err(_) => Ok(Location::Label(argument.to_owned())),
+        },
+        None => Err(Error::ArgumentInvalid { argument: String::new() }),
+    }
+}
+
+fn address(argument: Option<&str>) -> Result<u64, Error> {
+    let argument = argument.unwrap_or("");
+    u64::from_str_radix(argument.trim_start_matches("0x"), 16)
+        .map_err(|_| Error::ArgumentInvalid { argument: argument.to_owned() })
+}
+
+fn count(argument: Option<&str>, default: usize) -> Result<usize, Error> {
+    match argument {
+        Some(argument) => argument.parse::<usize>()
+            .map_err(|_| Error::ArgumentInvalid { argument: argument.to_owned() }),
+        None => Ok(default),
+    }
+}
+
+/// Parses a line of debugger input. Commands can be abbreviated as listed in
+/// `HELP`, and addresses are hexadecimal with an optional `0x` prefix. An
+/// empty line parses as `None`.
+pub fn parse(line: &str) -> Result<Option<Command>, Error> {
+    let mut words = line.split_whitespace();
+    let command = match words.next() {
+        Some(command) => command,
+        None => return Ok(None),
+    };
+    let argument = words.next();
+    Ok(Some(match command {
+        "b" | "break" => Command::Break(location(argument)?),
+        "cl" | "clear" => Command::Clear(location(argument)?),
+        "w" | "watch" => Command::Watch(address(argument)?),
+        "u" | "unwatch" => Command::Unwatch(address(argument)?),
+        "s" | "step" => Command::Step(count(argument, 1)?),
+        "n" | "next" => Command::Next,
+        "c" | "continue" => Command::Continue,
+        "st" | "stack" => Command::Stack,
+        "m" | "memory" => Command::Memory { address: address(argument)?, length: count(words.next(), 16)? },
+        "wh" | "where" => Command::Where,
+        "h" | "help" => Command::Help,
+        "q" | "quit" => Command::Quit,
+        _ => return Err(Error::CommandUnknown { command: command.to_owned() }),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_commands_and_their_abbreviations() {
+        assert_eq!(parse("b mul"), Ok(Some(Command::Break(Location::Label("mul".to_owned())))));
+        assert_eq!(parse("break 12"), Ok(Some(Command::Break(Location::Line(12)))));
+        assert_eq!(parse("cl 12"), Ok(Some(Command::Clear(Location::Line(12)))));
+        assert_eq!(parse("w 0x1f"), Ok(Some(Command::Watch(0x1f))));
+        assert_eq!(parse("unwatch ff"), Ok(Some(Command::Unwatch(0xff))));
+        assert_eq!(parse("s"), Ok(Some(Command::Step(1))));
+        assert_eq!(parse("step 10"), Ok(Some(Command::Step(10))));
+        assert_eq!(parse("  n  "), Ok(Some(Command::Next)));
+        assert_eq!(parse("c"), Ok(Some(Command::Continue)));
+        assert_eq!(parse("st"), Ok(Some(Command::Stack)));
+        assert_eq!(parse("m 80"), Ok(Some(Command::Memory { address: 0x80, length: 16 })));
+        assert_eq!(parse("memory 0x80 4"), Ok(Some(Command::Memory { address: 0x80, length: 4 })));
+        assert_eq!(parse("wh"), Ok(Some(Command::Where)));
+        assert_eq!(parse("h"), Ok(Some(Command::Help)));
+        assert_eq!(parse("quit"), Ok(Some(Command::Quit)));
+    }
+
+    #[test]
+    fn reads_an_empty_line_as_no_command() {
+        assert_eq!(parse(""), Ok(None));
+        assert_eq!(parse(" \t "), Ok(None));
+    }
+
+    #[test]
+    fn rejects_unknown_commands_and_bad_arguments() {
+        assert_eq!(parse("jump"), Err(Error::CommandUnknown { command: "jump".to_owned() }));
+        assert_eq!(parse("b"), Err(Error::ArgumentInvalid { argument: String::new() }));
+        assert_eq!(parse("w"), Err(Error::ArgumentInvalid { argument: String::new() }));
+        assert_eq!(parse("w 0xzz"), Err(Error::ArgumentInvalid { argument: "0xzz".to_owned() }));
+        assert_eq!(parse("s -1"), Err(Error::ArgumentInvalid { argument: "-1".to_owned() }));
+        assert_eq!(parse("m 80 lots"), Err(Error::ArgumentInvalid { argument: "lots".to_owned() }));
+    }
+}
@@ -0,0 +1,54 @@
+use std::fmt;
+use crate::emulator;
+use crate::reporter::{Diagnostic, Report, Reporter};
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    CommandUnknown { command: String },
+    ArgumentInvalid { argument: String },
+    LabelUnknown { label: String },
+    LineWithoutCode { line: usize },
+    Emulator(emulator::Error),
+}
+
+impl From<emulator::Error> for Error {
+    fn from(error: emulator::Error) -> Error {
+        Error::Emulator(error)
+    }
+}
+
+impl Report for Error {
+    fn report(&self, r: &Reporter) {
+        match &self {
+            Error::Emulator(error) => error.report(r),
+            _ => r.write(Diagnostic {
+                message: self.to_string(),
+                labels: vec![],
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Error::CommandUnknown { command } => write!(f,
+                "unknown command `{}`, try `help`", command,
+            ),
+            Error::ArgumentInvalid { argument } => write!(f,
+                "invalid argument `{}`", argument,
+            ),
+            Error::LabelUnknown { label } => write!(f,
+                "unknown label `{}`", label,
+            ),
+            Error::LineWithoutCode { line } => write!(f,
+                "no code on line {}", line,
+            ),
+            Error::Emulator(error) => write!(f,
+                "{}", error,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
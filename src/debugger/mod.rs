@@ -0,0 +1,263 @@
+pub use command::{Command, HELP, Location, parse};
+pub use error::Error;
+use codespan_reporting::files::{Files, SimpleFiles};
+use std::collections::{BTreeMap, BTreeSet};
+use super::emitter::{Assembly, Fragment};
+use super::emulator::{Emulator, Step};
+use super::{PUSH_OPCODE, SUBROUTINE_JUMP_OPCODE, Span, Symbol, YotType};
+
+mod command;
+mod error;
+
+/// Why execution stopped.
+#[derive(Debug, PartialEq)]
+pub enum Stop {
+    Stepped,
+    Breakpoint,
+    Watchpoint { address: u64, old: u8, new: u8 },
+    Halted,
+}
+
+/// Runs an assembled program in the emulator under the control of
+/// breakpoints and watchpoints, and maps addresses back to the source.
+pub struct Debugger {
+    pub emulator: Emulator,
+    fragments: Vec<Fragment>,
+    symbols: Vec<Symbol>,
+    /// The address of the first instruction of every source line.
+    lines: BTreeMap<usize, u64>,
+    breakpoints: BTreeSet<u64>,
+    /// The last seen byte at every watched address.
+    watchpoints: BTreeMap<u64, u8>,
+}
+
+impl Debugger {
+    pub fn new(assembly: Assembly, yot_type: YotType, files: &SimpleFiles<String, &str>) -> Result<Debugger, Error> {
+        let emulator = Emulator::new(&assembly.binary, yot_type)?;
+        let mut lines: BTreeMap<usize, u64> = BTreeMap::new();
+        for fragment in assembly.fragments.iter().filter(|fragment| fragment.size > 0) {
+            if let Some(span) = fragment.span {
+                if let Ok(line_index) = files.line_index(span.file_id, span.from.offset) {
+                    lines.entry(line_index + 1).or_insert(fragment.address as u64);
+                }
+            }
+        }
+        Ok(Debugger {
+            emulator,
+            fragments: assembly.fragments,
+            symbols: assembly.symbols,
+            lines,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeMap::new(),
+        })
+    }
+
+    /// The span of the source the byte at the given address was assembled
+    /// from, if any.
+    pub fn span(&self, address: u64) -> Option<Span> {
+        self.fragments.iter()
+            .find(|fragment| (fragment.address as u64..(fragment.address + fragment.size) as u64).contains(&address))
+            .and_then(|fragment| fragment.span)
+    }
+
+    pub fn address(&self, location: &Location) -> Result<u64, Error> {
+        match location {
+            Location::Label(label) => self.symbols.iter()
+                .find(|symbol| symbol.label == *label)
+                .map(|symbol| symbol.address as u64)
+                .ok_or_else(|| Error::LabelUnknown { label: label.clone() }),
+            Location::Line(line) => self.lines.get(line)
+                .copied()
+                .ok_or(Error::LineWithoutCode { line: *line }),
+        }
+    }
+
+    pub fn set_breakpoint(&mut self, location: &Location) -> Result<u64, Error> {
+        let address = self.address(location)?;
+        self.breakpoints.insert(address);
+        Ok(address)
+    }
+
+    pub fn clear_breakpoint(&mut self, location: &Location) -> Result<u64, Error> {
+        let address = self.address(location)?;
+        self.breakpoints.remove(&address);
+        Ok(address)
+    }
+
+    pub fn watch(&mut self, address: u64) {
+        let byte = self.emulator.memory.read(address);
+        self.watchpoints.insert(address, byte);
+    }
+
+    pub fn unwatch(&mut self, address: u64) {
+        self.watchpoints.remove(&address);
+    }
+
+    /// Executes a single instruction.
+    pub fn step(&mut self) -> Result<Stop, Error> {
+        if self.emulator.step()? == Step::Halted {
+            return Ok(Stop::Halted);
+        }
+        for (address, old) in self.watchpoints.iter_mut() {
+            let new = self.emulator.memory.read(*address);
+            if new != *old {
+                let stop = Stop::Watchpoint { address: *address, old: *old, new };
+                *old = new;
+                return Ok(stop);
+            }
+        }
+        if self.breakpoints.contains(&self.emulator.program_counter) {
+            return Ok(Stop::Breakpoint);
+        }
+        Ok(Stop::Stepped)
+    }
+
+    /// Finds the subroutine jump opcode the program counter is on, or that
+    /// follows the pushes of its target address.
+    fn subroutine_jump(&self) -> Option<u64> {
+        let memory = &self.emulator.memory;
+        let program_counter = self.emulator.program_counter;
+        (0..=self.emulator.yot_type as u64).rev().find_map(|pushes| {
+            let address = memory.wrap(program_counter.wrapping_add(pushes * 2));
+            let pushing = (0..pushes).all(|i| memory.read(program_counter.wrapping_add(i * 2)) == PUSH_OPCODE);
+            (pushing && memory.read(address) == SUBROUTINE_JUMP_OPCODE).then_some(address)
+        })
+    }
+
+    /// Executes a single instruction, or a whole subroutine jump including
+    /// the pushes of its target address and the subroutine itself. The
+    /// subroutine has returned once the instruction after the jump has been
+    /// reached as many times as the jump was taken, so that a recursive
+    /// subroutine calling itself through the same jump is stepped over as a
+    /// whole.
+    pub fn step_over(&mut self) -> Result<Stop, Error> {
+        let address = match self.subroutine_jump() {
+            Some(address) => address,
+            None => return self.step(),
+        };
+        let return_address = self.emulator.memory.wrap(address.wrapping_add(1));
+        let mut calls: usize = 0;
+        loop {
+            if self.emulator.program_counter == address {
+                calls += 1;
+            }
+            match self.step()? {
+                Stop::Stepped if self.emulator.program_counter == return_address => {
+                    calls = calls.saturating_sub(1);
+                    if calls == 0 {
+                        return Ok(Stop::Stepped);
+                    }
+                }
+                Stop::Stepped => (),
+                stop => return Ok(stop),
+            }
+        }
+    }
+
+    /// Executes instructions until a breakpoint, a watchpoint or `brk`.
+    pub fn resume(&mut self) -> Result<Stop, Error> {
+        loop {
+            match self.step()? {
+                Stop::Stepped => (),
+                stop => return Ok(stop),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::emitter::{Optimizations, emit};
+    use super::super::tokenizer::tokenize;
+    use super::super::{InstructionKind, get_opcode};
+
+    fn debugger(source: &'static str) -> Debugger {
+        let mut files = SimpleFiles::new();
+        let file_id = files.add("test.ysm".to_owned(), source);
+        let tokens = tokenize(source, file_id).unwrap();
+        let assembly = emit(&tokens, YotType::Y8, 0x80, None, &Optimizations::none()).unwrap();
+        Debugger::new(assembly, YotType::Y8, &files).unwrap()
+    }
+
+    #[test]
+    fn stops_at_breakpoints_by_label_and_by_line() {
+        let mut debugger = debugger("#01 #02 add\n:done\n#03\nbrk\n");
+        let done = debugger.set_breakpoint(&Location::Label("done".to_owned())).unwrap();
+        let brk = debugger.set_breakpoint(&Location::Line(4)).unwrap();
+        assert_eq!(debugger.address(&Location::Line(3)), Ok(done));
+
+        assert_eq!(debugger.resume(), Ok(Stop::Breakpoint));
+        assert_eq!((debugger.emulator.program_counter, debugger.emulator.stack()), (done, vec![0x03]));
+        assert_eq!(debugger.resume(), Ok(Stop::Breakpoint));
+        assert_eq!((debugger.emulator.program_counter, debugger.emulator.stack()), (brk, vec![0x03, 0x03]));
+        assert_eq!(debugger.resume(), Ok(Stop::Halted));
+    }
+
+    #[test]
+    fn clears_breakpoints() {
+        let mut debugger = debugger("#01 #02 add\n:done\n#03\nbrk\n");
+        debugger.set_breakpoint(&Location::Label("done".to_owned())).unwrap();
+        debugger.clear_breakpoint(&Location::Line(3)).unwrap();
+        assert_eq!(debugger.resume(), Ok(Stop::Halted));
+    }
+
+    #[test]
+    fn rejects_breakpoints_without_code() {
+        let mut debugger = debugger("#01\n:done\n\nbrk\n");
+        assert_eq!(debugger.set_breakpoint(&Location::Line(2)), Err(Error::LineWithoutCode { line: 2 }));
+        assert_eq!(debugger.set_breakpoint(&Location::Line(3)), Err(Error::LineWithoutCode { line: 3 }));
+        assert_eq!(debugger.set_breakpoint(&Location::Label("mul".to_owned())), Err(Error::LabelUnknown { label: "mul".to_owned() }));
+    }
+
+    #[test]
+    fn reports_old_and_new_values_at_watchpoints() {
+        let mut debugger = debugger("#2a @cell stm\n#2b @cell stm\n#2b @cell stm\nbrk\n:cell\nnop\n");
+        let cell = debugger.address(&Location::Label("cell".to_owned())).unwrap();
+        let nop = get_opcode(&InstructionKind::NoOperation);
+        debugger.watch(cell);
+
+        assert_eq!(debugger.resume(), Ok(Stop::Watchpoint { address: cell, old: nop, new: 0x2a }));
+        assert_eq!(debugger.resume(), Ok(Stop::Watchpoint { address: cell, old: 0x2a, new: 0x2b }));
+        // storing the same byte again changes nothing
+        assert_eq!(debugger.resume(), Ok(Stop::Halted));
+    }
+
+    #[test]
+    fn steps_over_subroutine_jumps() {
+        let mut debugger = debugger("five\nbrk\n:five\n#05 #01 swc jmp\n");
+        let brk = debugger.address(&Location::Line(2)).unwrap();
+        assert_eq!(debugger.step_over(), Ok(Stop::Stepped));
+        assert_eq!((debugger.emulator.program_counter, debugger.emulator.stack()), (brk, vec![0x05]));
+        assert_eq!(debugger.step_over(), Ok(Stop::Halted));
+    }
+
+    #[test]
+    fn steps_over_recursive_subroutine_jumps_in_the_same_call() {
+        // counts down to zero, calling itself once per step
+        let source = "
+            #03 countdown
+            brk
+            :countdown
+            #01 pck #00 equ @base bnc
+            #01 swc #01 sub
+            countdown
+            #01 swc jmp
+            :base
+            jmp
+        ";
+        let mut debugger = debugger(source);
+        let call = debugger.set_breakpoint(&Location::Line(7)).unwrap();
+        assert_eq!(debugger.resume(), Ok(Stop::Breakpoint));
+        debugger.clear_breakpoint(&Location::Line(7)).unwrap();
+        let depth = debugger.emulator.depth();
+
+        assert_eq!(debugger.step_over(), Ok(Stop::Stepped));
+        assert_eq!(debugger.address(&Location::Line(8)), Ok(debugger.emulator.program_counter));
+        assert_eq!(debugger.emulator.depth(), depth);
+        assert_eq!(debugger.emulator.stack()[1], 0x00);
+        assert_ne!(call, debugger.emulator.program_counter);
+        assert_eq!(debugger.resume(), Ok(Stop::Halted));
+        assert_eq!(debugger.emulator.stack(), [0x00]);
+    }
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+use crate::reporter::{Diagnostic, Report, Reporter};
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    SymbolMapMalformed { line_number: usize },
+    HeaderMissing,
+    BytesUnknown { count: usize },
+    ReassemblyFailed,
+    ReassemblyDiffers { address: usize },
+}
+
+impl Report for Error {
+    fn report(&self, r: &Reporter) {
+        let diagnostic = Diagnostic {
+            message: self.to_string(),
+            labels: vec![],
+        };
+        match &self {
+            Error::HeaderMissing | Error::BytesUnknown { .. } => r.warn(diagnostic),
+            _ => r.write(diagnostic),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Error::SymbolMapMalformed { line_number } => write!(f,
+                "malformed label map on line {}", line_number,
+            ),
+            Error::HeaderMissing => write!(f,
+                "the binary has no header, reassembling the disassembly won't reproduce it",
+            ),
+            Error::BytesUnknown { count } => write!(f,
+                "{} byte(s) could not be disassembled, reassembling the disassembly won't reproduce the binary", count,
+            ),
+            Error::ReassemblyFailed => write!(f,
+                "the disassembly could not be reassembled",
+            ),
+            Error::ReassemblyDiffers { address } => write!(f,
+                "reassembling the disassembly doesn't reproduce the binary, starting at address {:#x}", address,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
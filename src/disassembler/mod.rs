@@ -0,0 +1,391 @@
+pub use error::Error;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use super::{InstructionKind, PUSH_OPCODE, SUBROUTINE_JUMP_OPCODE, YotType, get_instruction_kind_by_opcode, get_mnemonic, read_address};
+use super::{emitter, tokenizer};
+
+mod error;
+
+const BYTES_PER_LITERAL: usize = 8;
+const TEXT_WIDTH: usize = 24;
+
+/// The stack pointer and entry point at the start of a binary.
+pub struct Header {
+    pub stack_pointer: u64,
+    pub entry_point: u64,
+}
+
+pub enum Item {
+    PrimitiveInstruction(InstructionKind),
+    DataLiteral(Vec<u8>),
+    /// An address pushed right before a `jmp` or `bnc`.
+    LabelLiteral(u64),
+    SubroutineJump(u64),
+    /// A byte that does not decode to anything the assembly language can
+    /// express: an unknown opcode, a subroutine jump opcode without a pushed
+    /// address before it or to an address no label can be defined at, or a
+    /// push opcode without its operand at the end of the binary.
+    Unknown(u8),
+}
+
+pub struct Line {
+    pub address: usize,
+    pub size: usize,
+    pub item: Item,
+}
+
+pub struct Disassembly {
+    pub yot_type: YotType,
+    pub header: Option<Header>,
+    pub lines: Vec<Line>,
+    /// The labels defined at each address, the first of which is the one
+    /// referred to.
+    pub labels: BTreeMap<u64, Vec<String>>,
+}
+
+impl Disassembly {
+    pub fn unknown_bytes(&self) -> usize {
+        self.lines.iter().filter(|line| matches!(line.item, Item::Unknown(_))).count()
+    }
+
+    fn label(&self, address: u64) -> Option<&str> {
+        self.labels.get(&address).and_then(|labels| labels.first()).map(|label| label.as_str())
+    }
+}
+
+/// Returns the header if the binary starts with one as written by
+/// `emitter::emit`, whose entry point directly follows the header.
+fn header(binary: &[u8], yot_type: YotType) -> Option<Header> {
+    let address_size = yot_type as usize;
+    if binary.len() < address_size * 2 {
+        return None;
+    }
+    let stack_pointer = read_address(&binary[..address_size]);
+    let entry_point = read_address(&binary[address_size..address_size * 2]);
+    if entry_point != (address_size * 2) as u64 {
+        return None;
+    }
+    Some(Header { stack_pointer, entry_point })
+}
+
+fn push_data_literal(lines: &mut Vec<Line>, address: usize, data: &[u8]) {
+    for (i, chunk) in data.chunks(BYTES_PER_LITERAL).enumerate() {
+        lines.push(Line {
+            address: address + i * BYTES_PER_LITERAL * 2,
+            size: chunk.len() * 2,
+            item: Item::DataLiteral(chunk.to_vec()),
+        });
+    }
+}
+
+/// Decodes the binary in a single linear sweep. Runs of push opcodes are
+/// folded into data literals, and the last address pushed right before a
+/// subroutine jump opcode becomes a subroutine jump, just like one pushed
+/// right before a `jmp` or `bnc` becomes a label literal. Bytes that cannot
+/// be decoded are kept as unknown items and decoding carries on after them.
+fn decode(binary: &[u8], start: usize, yot_type: YotType) -> Vec<Line> {
+    let address_size = yot_type as usize;
+    let mut lines: Vec<Line> = Vec::new();
+    let mut i: usize = start;
+
+    while i < binary.len() {
+        let start = i;
+        if binary[i] == PUSH_OPCODE && i + 1 < binary.len() {
+            let mut data: Vec<u8> = Vec::new();
+            while i + 1 < binary.len() && binary[i] == PUSH_OPCODE {
+                data.push(binary[i + 1]);
+                i += 2;
+            }
+            let next_opcode = binary.get(i).copied();
+            let is_jump = matches!(
+                next_opcode.and_then(get_instruction_kind_by_opcode),
+                Some(InstructionKind::Jump) | Some(InstructionKind::Branch)
+            );
+            if (next_opcode == Some(SUBROUTINE_JUMP_OPCODE) || is_jump) && data.len() >= address_size {
+                let (data, address) = data.split_at(data.len() - address_size);
+                push_data_literal(&mut lines, start, data);
+                let address = read_address(address);
+                if is_jump {
+                    lines.push(Line {
+                        address: start + data.len() * 2,
+                        size: address_size * 2,
+                        item: Item::LabelLiteral(address),
+                    });
+                } else {
+                    lines.push(Line {
+                        address: start + data.len() * 2,
+                        size: address_size * 2 + 1,
+                        item: Item::SubroutineJump(address),
+                    });
+                    i += 1;
+                }
+            } else {
+                push_data_literal(&mut lines, start, &data);
+            }
+            continue;
+        }
+        let item = match get_instruction_kind_by_opcode(binary[i]) {
+            Some(instruction_kind) => Item::PrimitiveInstruction(instruction_kind),
+            None => Item::Unknown(binary[i]),
+        };
+        lines.push(Line { address: i, size: 1, item });
+        i += 1;
+    }
+
+    lines
+}
+
+/// Splits data literals so that every address in `addresses` that points at
+/// one of their push opcodes starts a line of its own.
+fn split_data_literals(lines: Vec<Line>, addresses: &BTreeSet<u64>) -> Vec<Line> {
+    let mut split: Vec<Line> = Vec::with_capacity(lines.len());
+    for line in lines.into_iter() {
+        let data = match line.item {
+            Item::DataLiteral(data) => data,
+            item => {
+                split.push(Line { item, ..line });
+                continue;
+            }
+        };
+        let mut start: usize = 0;
+        for address in addresses.range(line.address as u64 + 1..(line.address + line.size) as u64) {
+            let offset = *address as usize - line.address;
+            if offset.is_multiple_of(2) {
+                split.push(Line {
+                    address: line.address + start * 2,
+                    size: offset - start * 2,
+                    item: Item::DataLiteral(data[start..offset / 2].to_vec()),
+                });
+                start = offset / 2;
+            }
+        }
+        split.push(Line {
+            address: line.address + start * 2,
+            size: line.size - start * 2,
+            item: Item::DataLiteral(data[start..].to_vec()),
+        });
+    }
+    split
+}
+
+/// Disassembles the binary, recovering a label for every jump target and
+/// every address named in `names`, e.g. the entries of a label map. Labels
+/// can only be defined at the start of a line, so targets pointing into the
+/// middle of one stay plain addresses.
+pub fn disassemble(binary: &[u8], yot_type: YotType, names: &[(u64, String)]) -> Disassembly {
+    let header = header(binary, yot_type);
+    let start = if header.is_some() { yot_type as usize * 2 } else { 0 };
+    let lines = decode(binary, start, yot_type);
+
+    let targets: BTreeSet<u64> = lines.iter()
+        .filter_map(|line| match line.item {
+            Item::LabelLiteral(address) | Item::SubroutineJump(address) => Some(address),
+            _ => None,
+        })
+        .chain(names.iter().map(|(address, _)| *address))
+        .collect();
+    let lines = split_data_literals(lines, &targets);
+
+    let mut labels: BTreeMap<u64, Vec<String>> = BTreeMap::new();
+    let line_addresses: BTreeSet<u64> = lines.iter()
+        .map(|line| line.address as u64)
+        .chain(std::iter::once(binary.len() as u64))
+        .filter(|address| *address >= start as u64)
+        .collect();
+    for (address, label) in names.iter() {
+        if line_addresses.contains(address) {
+            labels.entry(*address).or_default().push(label.clone());
+        }
+    }
+    for address in targets.intersection(&line_addresses) {
+        labels.entry(*address).or_insert_with(|| vec![label_name(*address, yot_type)]);
+    }
+
+    // a subroutine jump can only be written with a label, so one to any other
+    // address is left as the pushed address and an unknown opcode
+    let address_size = yot_type as usize;
+    let lines = lines.into_iter()
+        .flat_map(|line| match line.item {
+            Item::SubroutineJump(address) if !labels.contains_key(&address) => vec![
+                Line {
+                    address: line.address,
+                    size: address_size * 2,
+                    item: Item::DataLiteral((0..address_size).rev().map(|i| (address >> (i * 8)) as u8).collect()),
+                },
+                Line {
+                    address: line.address + address_size * 2,
+                    size: 1,
+                    item: Item::Unknown(SUBROUTINE_JUMP_OPCODE),
+                },
+            ],
+            item => vec![Line { item, ..line }],
+        })
+        .collect();
+
+    Disassembly { yot_type, header, lines, labels }
+}
+
+/// Names an address that has no name of its own.
+pub fn label_name(address: u64, yot_type: YotType) -> String {
+    format!("l_{:0width$x}", address, width = yot_type as usize * 2)
+}
+
+/// Parses the labels and their addresses from a label map in the text
+/// format written by `map::text`.
+pub fn parse_map(map: &str) -> Result<Vec<(u64, String)>, Error> {
+    map.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with(';'))
+        .map(|(i, line)| {
+            let mut fields = line.split_whitespace();
+            match (fields.next().map(|address| u64::from_str_radix(address, 16)), fields.next(), fields.next()) {
+                (Some(Ok(address)), Some(_), Some(label)) => Ok((address, label.to_owned())),
+                _ => Err(Error::SymbolMapMalformed { line_number: i + 1 }),
+            }
+        })
+        .collect()
+}
+
+/// Checks that assembling the disassembly with the stack pointer from its
+/// header reproduces the binary byte for byte.
+pub fn verify(disassembly: &Disassembly, binary: &[u8]) -> Result<(), Error> {
+    let header = disassembly.header.as_ref().ok_or(Error::HeaderMissing)?;
+    match disassembly.unknown_bytes() {
+        0 => (),
+        count => return Err(Error::BytesUnknown { count }),
+    }
+    let source = disassembly.to_string();
+    let tokens = tokenizer::tokenize(&source, 0).map_err(|_| Error::ReassemblyFailed)?;
+    let assembly = emitter::emit(&tokens, disassembly.yot_type, header.stack_pointer, None, &emitter::Optimizations::none()).map_err(|_| Error::ReassemblyFailed)?;
+    match assembly.binary.iter().zip(binary.iter()).position(|(a, b)| a != b) {
+        Some(address) => Err(Error::ReassemblyDiffers { address }),
+        None if assembly.binary.len() != binary.len() => Err(Error::ReassemblyDiffers { address: assembly.binary.len().min(binary.len()) }),
+        None => Ok(()),
+    }
+}
+
+impl fmt::Display for Disassembly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self.yot_type as usize * 2;
+        writeln!(f, "; {} disassembly", self.yot_type)?;
+        if let Some(header) = &self.header {
+            writeln!(f, "; stack pointer: {:#0width$x} (assemble with --sp {})", header.stack_pointer, header.stack_pointer, width = width + 2)?;
+            writeln!(f, "; entry point: {:#0width$x}", header.entry_point, width = width + 2)?;
+        }
+        let mut labels = self.labels.iter().peekable();
+        for line in self.lines.iter() {
+            while let Some((_, names)) = labels.next_if(|(address, _)| **address <= line.address as u64) {
+                for name in names.iter() {
+                    writeln!(f, ":{}", name)?;
+                }
+            }
+            let text = match &line.item {
+                Item::PrimitiveInstruction(instruction_kind) => get_mnemonic(instruction_kind).to_owned(),
+                Item::DataLiteral(data) => format!("#{}", data.iter().map(|byte| format!("{:02x}", byte)).collect::<String>()),
+                Item::LabelLiteral(address) => match self.label(*address) {
+                    Some(label) => format!("@{}", label),
+                    None => format!("#{:0width$x}", address, width = width),
+                },
+                // `disassemble` only leaves subroutine jumps to labelled
+                // addresses, anything else makes `verify` fail
+                Item::SubroutineJump(address) => match self.label(*address) {
+                    Some(label) => label.to_owned(),
+                    None => label_name(*address, self.yot_type),
+                },
+                Item::Unknown(byte) => {
+                    writeln!(f, "; {:0width$x}  unknown byte {:#04x}", line.address, byte, width = width)?;
+                    continue;
+                }
+            };
+            writeln!(f, "{:<text_width$}; {:0width$x}", text, line.address, text_width = TEXT_WIDTH, width = width)?;
+        }
+        for (_, names) in labels {
+            for name in names.iter() {
+                writeln!(f, ":{}", name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use codespan_reporting::files::SimpleFiles;
+    use super::super::{get_opcode, map};
+
+    const SOURCE: &str = "
+        #0304 mul
+        #00 @done bnc
+        :done
+        brk
+        :mul
+        #02 ins
+        #00 pck #00 equ @end bnc
+        @mul jmp
+        :end
+        drp jmp
+        :table
+        #000102030405060708
+    ";
+
+    fn assemble(yot_type: YotType) -> emitter::Assembly {
+        let tokens = tokenizer::tokenize(SOURCE, 0).unwrap();
+        emitter::emit(&tokens, yot_type, 0x80, None, &emitter::Optimizations::none()).unwrap()
+    }
+
+    fn reassemble(disassembly: &Disassembly) -> Vec<u8> {
+        let tokens = tokenizer::tokenize(&disassembly.to_string(), 0).unwrap();
+        let stack_pointer = disassembly.header.as_ref().unwrap().stack_pointer;
+        emitter::emit(&tokens, disassembly.yot_type, stack_pointer, None, &emitter::Optimizations::none()).unwrap().binary
+    }
+
+    const YOT_TYPES: [YotType; 4] = [YotType::Y8, YotType::Y16, YotType::Y32, YotType::Y64];
+
+    #[test]
+    fn reassembles_into_the_same_binary() {
+        for yot_type in YOT_TYPES {
+            let binary = assemble(yot_type).binary;
+            let disassembly = disassemble(&binary, yot_type, &[]);
+            assert_eq!(reassemble(&disassembly), binary, "{}", yot_type);
+            assert_eq!(verify(&disassembly, &binary), Ok(()), "{}", yot_type);
+        }
+    }
+
+    #[test]
+    fn reassembles_into_the_same_binary_with_names_from_a_label_map() {
+        for yot_type in YOT_TYPES {
+            let assembly = assemble(yot_type);
+            let mut files = SimpleFiles::new();
+            files.add("in.ysm".to_owned(), SOURCE);
+            let names = parse_map(&map::text(&assembly.symbols, yot_type, &files)).unwrap();
+            assert_eq!(names.len(), 4);
+
+            let disassembly = disassemble(&assembly.binary, yot_type, &names);
+            let source = disassembly.to_string();
+            for label in [":done", ":mul", ":end", ":table", "@end", "@mul"] {
+                assert!(source.contains(label), "{}: {}", yot_type, label);
+            }
+            assert_eq!(reassemble(&disassembly), assembly.binary, "{}", yot_type);
+        }
+    }
+
+    #[test]
+    fn keeps_subroutine_jumps_into_the_middle_of_a_line_as_unknown_bytes() {
+        let brk = get_opcode(&InstructionKind::Break);
+        // the pushed address points at the operand of its own push
+        let binary = [0x80, 0x02, PUSH_OPCODE, 0x03, SUBROUTINE_JUMP_OPCODE, brk];
+        let disassembly = disassemble(&binary, YotType::Y8, &[]);
+        assert!(disassembly.labels.is_empty());
+        assert!(!disassembly.to_string().contains(&label_name(0x03, YotType::Y8)));
+        assert_eq!(verify(&disassembly, &binary), Err(Error::BytesUnknown { count: 1 }));
+    }
+
+    #[test]
+    fn reports_binaries_without_a_header() {
+        let brk = get_opcode(&InstructionKind::Break);
+        let disassembly = disassemble(&[PUSH_OPCODE, 0x01, brk], YotType::Y8, &[]);
+        assert!(disassembly.header.is_none());
+        assert_eq!(verify(&disassembly, &[PUSH_OPCODE, 0x01, brk]), Err(Error::HeaderMissing));
+    }
+}
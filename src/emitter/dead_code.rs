@@ -0,0 +1,179 @@
+use super::{Address, Atom, Span, Spanned, AtomStream, ConstantKind, Error, InstructionKind, Offset, YotType};
+use std::collections::HashMap;
+
+pub struct RemovedBlock {
+    pub label: String,
+    pub size: usize,
+}
+
+struct Block<'a> {
+    label: Option<&'a str>,
+    atoms: &'a [Atom],
+    spans: &'a [Option<Span>],
+}
+
+impl<'a> Block<'a> {
+    fn references(&self) -> impl Iterator<Item = &'a str> {
+        self.atoms.iter().filter_map(|atom: &'a Atom| -> Option<&'a str> {
+            match atom {
+                Atom::Addressable(Address::Deferred(label), _) => Some(&label.node),
+                _ => None,
+            }
+        })
+    }
+
+    fn falls_through(&self) -> bool {
+        !matches!(
+            self.atoms.last(),
+            Some(Atom::Constant(ConstantKind::PrimitiveInstruction(InstructionKind::Jump)))
+                | Some(Atom::Constant(ConstantKind::PrimitiveInstruction(InstructionKind::Break)))
+        )
+    }
+
+    fn size(&self) -> Offset {
+        self.atoms.iter().fold(Offset::new(), |size, atom| size + atom.size())
+    }
+}
+
+fn split<'a>(atoms: &'a [Atom], spans: &'a [Option<Span>]) -> Vec<Block<'a>> {
+    let mut blocks: Vec<Block> = Vec::new();
+    let mut label: Option<&str> = None;
+    let mut start: usize = 0;
+
+    for (i, atom) in atoms.iter().enumerate() {
+        if let Atom::LabelDefinition(Spanned { node: next_label, .. }) = atom {
+            blocks.push(Block { label, atoms: &atoms[start..i], spans: &spans[start..i] });
+            label = Some(next_label);
+            start = i;
+        }
+    }
+    blocks.push(Block { label, atoms: &atoms[start..], spans: &spans[start..] });
+
+    blocks
+}
+
+/// Removes every label-delimited block that cannot be reached from the
+/// entry point. A block is reachable if it is the entry block, if its label
+/// is kept explicitly, if a reachable block refers to its label through a
+/// label literal or a subroutine jump, or if the preceding block is
+/// reachable and does not end with an unconditional `jmp` or `brk`.
+pub(super) fn eliminate(atom_stream: &AtomStream, kept_labels: &[String], yot_type: YotType) -> Result<(AtomStream, Vec<RemovedBlock>), Vec<Error>> {
+    let blocks = split(atom_stream.atoms(), atom_stream.spans());
+    let block_indices: HashMap<&str, usize> = blocks.iter().enumerate()
+        .filter_map(|(i, block)| block.label.map(|label| (label, i)))
+        .collect();
+
+    let mut errors: Vec<Error> = Vec::new();
+    let mut reachable: Vec<bool> = vec![false; blocks.len()];
+    let mut worklist: Vec<usize> = vec![0];
+
+    for label in kept_labels.iter() {
+        match block_indices.get(label.as_str()) {
+            Some(i) => worklist.push(*i),
+            None => errors.push(Error::CannotFindKeptLabel { label: label.to_owned() }),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    while let Some(i) = worklist.pop() {
+        if reachable[i] {
+            continue;
+        }
+        reachable[i] = true;
+        let block = &blocks[i];
+        // references to undefined labels are reported when rendering
+        worklist.extend(block.references().filter_map(|label| block_indices.get(label).copied()));
+        if block.falls_through() && i + 1 < blocks.len() {
+            worklist.push(i + 1);
+        }
+    }
+
+    let mut stripped: AtomStream = AtomStream::new();
+    let mut removed_blocks: Vec<RemovedBlock> = Vec::new();
+
+    for (block, reachable) in blocks.iter().zip(reachable.iter()) {
+        if *reachable {
+            for (atom, span) in block.atoms.iter().zip(block.spans.iter()) {
+                stripped.push(atom.clone(), *span);
+            }
+        } else if let Some(label) = block.label {
+            removed_blocks.push(RemovedBlock { label: label.to_owned(), size: block.size().render(yot_type) });
+        }
+    }
+
+    Ok((stripped, removed_blocks))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::tokenizer::tokenize;
+    use super::super::{Assembly, Optimizations, emit};
+    use super::*;
+
+    fn strip(source: &str, kept_labels: &[&str], yot_type: YotType) -> Result<Assembly, Vec<Error>> {
+        let tokens = tokenize(source, 0).unwrap();
+        emit(&tokens, yot_type, 0x80, None, &Optimizations {
+            strip_dead_code: true,
+            kept_labels: kept_labels.iter().map(|label| label.to_string()).collect(),
+            ..Optimizations::none()
+        })
+    }
+
+    fn removed_labels(assembly: &Assembly) -> Vec<&str> {
+        assembly.removed_blocks.iter().map(|removed_block| removed_block.label.as_str()).collect()
+    }
+
+    #[test]
+    fn keeps_blocks_reached_by_falling_through() {
+        let assembly = strip("nop\n:a\nnop\n:b\nnop jmp\n:c\nnop\n", &[], YotType::Y8).unwrap();
+        assert_eq!(removed_labels(&assembly), ["c"]);
+    }
+
+    #[test]
+    fn ends_blocks_at_jmp_and_brk() {
+        let assembly = strip("brk\n:a\nnop nop\n:b\njmp\n:c\nnop brk\n", &[], YotType::Y8).unwrap();
+        assert_eq!(removed_labels(&assembly), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn keeps_blocks_that_are_only_referred_to() {
+        let source = "
+            @jumped jmp
+            :unused
+            nop jmp
+            :jumped
+            called brk
+            :called
+            jmp
+        ";
+        let assembly = strip(source, &[], YotType::Y8).unwrap();
+        assert_eq!(removed_labels(&assembly), ["unused"]);
+        let symbols: Vec<&str> = assembly.symbols.iter().map(|symbol| symbol.label.as_str()).collect();
+        assert_eq!(symbols, ["jumped", "called"]);
+    }
+
+    #[test]
+    fn keeps_labels_given_with_keep() {
+        let source = "brk\n:handler\nnop\n:next\njmp\n:dead\njmp\n";
+        let assembly = strip(source, &["handler"], YotType::Y8).unwrap();
+        assert_eq!(removed_labels(&assembly), ["dead"]);
+
+        let errs = strip(source, &["handler", "missing"], YotType::Y8).err().unwrap();
+        assert_eq!(errs, [Error::CannotFindKeptLabel { label: "missing".to_owned() }]);
+    }
+
+    #[test]
+    fn reports_the_size_of_removed_blocks() {
+        let source = "brk\n:dead\n#0102 @dead dead jmp\n";
+        for yot_type in [YotType::Y8, YotType::Y16, YotType::Y32, YotType::Y64] {
+            let kept = strip(source, &["dead"], yot_type).unwrap();
+            let stripped = strip(source, &[], yot_type).unwrap();
+            let size = 4 + yot_type as usize * 2 + (yot_type as usize * 2 + 1) + 1;
+            assert_eq!(stripped.removed_blocks[0].size, size, "{}", yot_type);
+            assert_eq!(kept.binary.len() - stripped.binary.len(), size, "{}", yot_type);
+        }
+    }
+}
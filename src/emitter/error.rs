@@ -13,6 +13,9 @@ pub enum Error {
         label: String,
         span: Span,
     },
+    CannotFindKeptLabel {
+        label: String,
+    },
     BinaryTooLarge {
         current_size: usize,
         requested_size: usize,
@@ -42,6 +45,10 @@ impl Report for Error {
                     message: "not found in this scope".to_owned(),
                 }],
             }),
+            Error::CannotFindKeptLabel { label } => r.write(Diagnostic {
+                message: format!("cannot find label `{}` given with `--keep {}`", label, label),
+                labels: vec![],
+            }),
             Error::BinaryTooLarge { current_size, requested_size } => r.write(Diagnostic {
                 message: format!("binary of size {} does not fit within the requested size constraint of {}", current_size, requested_size),
                 labels: vec![],
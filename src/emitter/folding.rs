@@ -0,0 +1,86 @@
+use super::{Atom, AtomStream, ConstantKind, Rewrite, evaluate_pure, record};
+
+const CONSTANT_FOLD: &str = "constant-fold";
+
+/// Replaces pure primitive instructions whose both operands are pushed by
+/// data literals right before them with the pushed result. Folded results
+/// become data literals themselves, so whole expressions such as
+/// `#03 #04 add #02 shl` collapse into a single literal. A label definition
+/// between the operands and the instruction stops the folding, since the
+/// instruction may be reached with a different stack through the label.
+pub(super) fn fold(atom_stream: &AtomStream, rewrites: &mut Vec<Rewrite>) -> AtomStream {
+    let mut folded: AtomStream = AtomStream::new();
+
+    for (atom, span) in atom_stream.atoms().iter().zip(atom_stream.spans().iter()) {
+        if let Atom::Constant(ConstantKind::PrimitiveInstruction(instruction_kind)) = atom {
+            let result = operands(&folded).and_then(|(a, b)| evaluate_pure(instruction_kind, a, b));
+            if let Some(result) = result {
+                let size_before = folded.offset().constant + atom.size().constant;
+                folded.pop_literal_byte();
+                folded.pop_literal_byte();
+                folded.push_literal_byte(result, *span);
+                record(rewrites, CONSTANT_FOLD, size_before - folded.offset().constant);
+                continue;
+            }
+        }
+        folded.push(atom.clone(), *span);
+    }
+
+    folded
+}
+
+/// Returns the two topmost bytes pushed by the data literals at the end of
+/// the stream, the second operand being the last pushed one.
+fn operands(atom_stream: &AtomStream) -> Option<(u8, u8)> {
+    match atom_stream.atoms().last() {
+        Some(Atom::Constant(ConstantKind::DataLiteral(data))) if data.len() >= 2 => {
+            Some((data[data.len() - 2], data[data.len() - 1]))
+        }
+        Some(Atom::Constant(ConstantKind::DataLiteral(data))) => {
+            atom_stream.last_literal_byte(1).map(|a| (a, data[0]))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::tokenizer::tokenize;
+    use super::super::super::YotType;
+    use super::super::{Optimizations, emit};
+
+    fn assemble(source: &str, fold_constants: bool) -> super::super::Assembly {
+        let tokens = tokenize(source, 0).unwrap();
+        emit(&tokens, YotType::Y16, 0x1000, None, &Optimizations { fold_constants, ..Optimizations::none() }).unwrap()
+    }
+
+    /// Checks that `source` is folded into the same binary as `expected` and
+    /// returns the number of folds and the bytes they saved.
+    fn fold(source: &str, expected: &str) -> Option<(usize, usize)> {
+        let folded = assemble(source, true);
+        assert_eq!(folded.binary, assemble(expected, false).binary, "{}", source);
+        folded.rewrites.iter().map(|rewrite| (rewrite.count, rewrite.saved_bytes)).next()
+    }
+
+    #[test]
+    fn folds_chains_of_pure_instructions() {
+        assert_eq!(fold("#03 #04 add", "#07"), Some((1, 3)));
+        assert_eq!(fold("#03 #04 add #02 shl", "#1c"), Some((2, 6)));
+        assert_eq!(fold("#0a #03 #04 add sub nop", "#03 nop"), Some((2, 6)));
+        assert_eq!(fold("#010203 xor", "#0101"), Some((1, 3)));
+    }
+
+    #[test]
+    fn never_folds_across_label_definitions() {
+        assert_eq!(fold("#03 #04 :loop add", "#03 #04 :loop add"), None);
+        assert_eq!(fold("#03 :loop #04 add", "#03 :loop #04 add"), None);
+        assert_eq!(fold("#03 :loop #04 #05 add add", "#03 :loop #09 add"), Some((1, 3)));
+    }
+
+    #[test]
+    fn leaves_impure_instructions_and_other_operands_alone() {
+        for source in ["#03 #04 adc", "#03 #04 sbb", "#03 #04 swc", "#03 #04 pck", "#03 #04 jmp", "add #04 add", "@end #04 add :end"] {
+            assert_eq!(fold(source, source), None, "{}", source);
+        }
+    }
+}
@@ -1,15 +1,18 @@
 use super::span::{Span, Spanned, Spanning};
 use super::YotType;
 use super::Token;
+use super::tokenizer::token::StackPattern;
 use std::collections::HashMap;
 pub use error::Error;
-use super::get_opcode;
+pub use dead_code::RemovedBlock;
+use super::{PUSH_OPCODE, SUBROUTINE_JUMP_OPCODE, evaluate_pure, get_opcode};
 use super::InstructionKind;
 
+mod dead_code;
 mod error;
-
-const PUSH_OPCODE: u8 = 0x20;
-const SUBROUTINE_JUMP_OPCODE: u8 = 0x72;
+mod folding;
+mod peephole;
+pub mod tail_call;
 
 #[derive(Copy, Clone)]
 struct Offset {
@@ -30,7 +33,7 @@ impl Offset {
     }
 }
 
-impl<'a> std::ops::Add<Offset> for Offset {
+impl std::ops::Add<Offset> for Offset {
     type Output = Self;
 
     fn add(self, rhs: Self) -> Self {
@@ -41,10 +44,46 @@ impl<'a> std::ops::Add<Offset> for Offset {
     }
 }
 
+impl std::ops::Sub<Offset> for Offset {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Offset {
+            constant: self.constant - rhs.constant,
+            yot_type_dependent: self.yot_type_dependent - rhs.yot_type_dependent,
+        }
+    }
+}
+
+/// Joins the spans of two atoms merged into one. Spans from different files
+/// cannot be joined, in which case the first one is kept.
+fn combine(first: Option<Span>, second: Option<Span>) -> Option<Span> {
+    match (first, second) {
+        (Some(first), Some(second)) if first.file_id == second.file_id => Some(Span::combine(&first, &second)),
+        (Some(first), _) => Some(first),
+        (None, second) => second,
+    }
+}
+
 #[derive(Clone)]
 enum Atom {
     Constant(ConstantKind),
-    Addressable(Address, AddressableKind)
+    Addressable(Address, AddressableKind),
+    LabelDefinition(Spanned<String>),
+    StackAssertion(StackPattern),
+}
+
+impl Atom {
+    pub fn size(&self) -> Offset {
+        match self {
+            Atom::Constant(ConstantKind::PrimitiveInstruction(_)) => Offset { constant: 1, yot_type_dependent: 0 },
+            Atom::Constant(ConstantKind::DataLiteral(data)) => Offset { constant: data.len() * 2, yot_type_dependent: 0 },
+            Atom::Constant(ConstantKind::AddressLiteral(_)) => Offset { constant: 0, yot_type_dependent: 1 },
+            Atom::Addressable(_, AddressableKind::LabelLiteral) => Offset { constant: 0, yot_type_dependent: 2 },
+            Atom::Addressable(_, AddressableKind::SubroutineJump) => Offset { constant: 1, yot_type_dependent: 2 },
+            Atom::LabelDefinition(_) | Atom::StackAssertion(_) => Offset::new(),
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -66,9 +105,12 @@ enum Address {
     Resolved(Offset),
 }
 
+/// A sequence of atoms along with the spans of the tokens they were emitted
+/// from. Only the atoms of the header have no span.
 #[derive(Clone)]
 struct AtomStream {
     atoms: Vec<Atom>,
+    spans: Vec<Option<Span>>,
     offset: Offset,
 }
 
@@ -76,54 +118,135 @@ impl AtomStream {
     pub fn new() -> AtomStream {
         AtomStream {
             atoms: Vec::new(),
+            spans: Vec::new(),
             offset: Offset::new(),
         }
     }
 
-    pub fn push_primitive_instruction(&mut self, instruction_kind: InstructionKind) {
-        self.atoms.push(Atom::Constant(ConstantKind::PrimitiveInstruction(instruction_kind)));
-        self.offset.constant += 1;
+    pub fn push(&mut self, atom: Atom, span: Option<Span>) {
+        self.offset = self.offset + atom.size();
+        self.atoms.push(atom);
+        self.spans.push(span);
     }
 
-    pub fn push_data_literal(&mut self, data: Vec<u8>) {
-        self.offset.constant += data.len() * 2;
-        self.atoms.push(Atom::Constant(ConstantKind::DataLiteral(data)));
+    pub fn pop(&mut self) -> Option<(Atom, Option<Span>)> {
+        let atom = self.atoms.pop()?;
+        let span = self.spans.pop()?;
+        self.offset = self.offset - atom.size();
+        Some((atom, span))
+    }
+
+    /// Returns the last byte of the data literal `index_from_end` atoms away
+    /// from the end of the stream, if that atom is a data literal.
+    pub fn last_literal_byte(&self, index_from_end: usize) -> Option<u8> {
+        match self.atoms.len().checked_sub(index_from_end + 1).and_then(|index| self.atoms.get(index)) {
+            Some(Atom::Constant(ConstantKind::DataLiteral(data))) => data.last().copied(),
+            _ => None,
+        }
+    }
+
+    pub fn pop_literal_byte(&mut self) -> Option<u8> {
+        match self.atoms.last() {
+            Some(Atom::Constant(ConstantKind::DataLiteral(_))) => (),
+            _ => return None,
+        }
+        if let Some((Atom::Constant(ConstantKind::DataLiteral(mut data)), span)) = self.pop() {
+            let byte = data.pop();
+            if !data.is_empty() {
+                self.push(Atom::Constant(ConstantKind::DataLiteral(data)), span);
+            }
+            byte
+        } else {
+            None
+        }
+    }
+
+    /// Pushes a byte onto the data literal at the end of the stream, or a new
+    /// data literal if there is none, extending its span up to `span`.
+    pub fn push_literal_byte(&mut self, byte: u8, span: Option<Span>) {
+        if let Some(Atom::Constant(ConstantKind::DataLiteral(_))) = self.atoms.last() {
+            if let Some((Atom::Constant(ConstantKind::DataLiteral(mut data)), previous_span)) = self.pop() {
+                data.push(byte);
+                self.push(Atom::Constant(ConstantKind::DataLiteral(data)), combine(previous_span, span));
+            }
+        } else {
+            self.push(Atom::Constant(ConstantKind::DataLiteral(vec![byte])), span);
+        }
+    }
+
+    pub fn push_primitive_instruction(&mut self, instruction_kind: InstructionKind, span: Span) {
+        self.push(Atom::Constant(ConstantKind::PrimitiveInstruction(instruction_kind)), Some(span));
+    }
+
+    pub fn push_data_literal(&mut self, data: Vec<u8>, span: Span) {
+        self.push(Atom::Constant(ConstantKind::DataLiteral(data)), Some(span));
     }
 
     pub fn push_address_literal(&mut self, address: u64) {
-        self.atoms.push(Atom::Constant(ConstantKind::AddressLiteral(address)));
-        self.offset.yot_type_dependent += 1;
+        self.push(Atom::Constant(ConstantKind::AddressLiteral(address)), None);
     }
 
     pub fn push_label_literal(&mut self, label: String, span: Span) {
-        self.atoms.push(Atom::Addressable(Address::Deferred(label.spanning(span)), AddressableKind::LabelLiteral));
-        self.offset.yot_type_dependent += 2;
+        self.push(Atom::Addressable(Address::Deferred(label.spanning(span)), AddressableKind::LabelLiteral), Some(span));
     }
 
     pub fn push_subroutine_jump(&mut self, label: String, span: Span) {
-        self.atoms.push(Atom::Addressable(Address::Deferred(label.spanning(span)), AddressableKind::SubroutineJump));
-        self.offset.constant += 1;
-        self.offset.yot_type_dependent += 2;
+        self.push(Atom::Addressable(Address::Deferred(label.spanning(span)), AddressableKind::SubroutineJump), Some(span));
     }
 
-    pub fn offseted(&self, offset: Offset) -> AtomStream {
-        AtomStream {
-            atoms: self.atoms.iter().map(|atom: &Atom| -> Atom {
-                match atom {
-                    Atom::Addressable(Address::Resolved(o), kind) => Atom::Addressable(Address::Resolved(offset + *o), *kind),
-                    x => x.clone(),
+    pub fn push_label_definition(&mut self, label: String, span: Span) {
+        self.push(Atom::LabelDefinition(label.spanning(span)), Some(span));
+    }
+
+    pub fn push_stack_assertion(&mut self, pattern: StackPattern, span: Span) {
+        self.push(Atom::StackAssertion(pattern), Some(span));
+    }
+
+    pub fn label_definitions(&self) -> HashMap<String, Offset> {
+        let mut label_definitions: HashMap<String, Offset> = HashMap::new();
+        let mut offset: Offset = Offset::new();
+        for atom in self.atoms.iter() {
+            if let Atom::LabelDefinition(Spanned { node: label, .. }) = atom {
+                label_definitions.insert(label.clone(), offset);
+            }
+            offset = offset + atom.size();
+        }
+        label_definitions
+    }
+
+    /// Lists the labels along with their addresses and the sizes of the
+    /// blocks they delimit, each reaching up to the next label or the end
+    /// of the stream.
+    pub fn symbols(&self, yot_type: YotType) -> Vec<Symbol> {
+        let mut symbols: Vec<Symbol> = Vec::new();
+        let mut offset: Offset = Offset::new();
+        for atom in self.atoms.iter() {
+            if let Atom::LabelDefinition(Spanned { node: label, span }) = atom {
+                let address = offset.render(yot_type);
+                if let Some(previous_symbol) = symbols.last_mut() {
+                    previous_symbol.size = address - previous_symbol.address;
                 }
-            }).collect::<Vec<Atom>>(),
-            offset: self.offset,
+                symbols.push(Symbol {
+                    label: label.clone(),
+                    address,
+                    size: 0,
+                    span: *span,
+                });
+            }
+            offset = offset + atom.size();
+        }
+        if let Some(last_symbol) = symbols.last_mut() {
+            last_symbol.size = offset.render(yot_type) - last_symbol.address;
         }
+        symbols
     }
 
-    pub fn resolved(self, label_definitions: &HashMap<String, (Offset, Span)>) -> AtomStream {
+    pub fn resolved(self, label_definitions: &HashMap<String, Offset>) -> AtomStream {
         AtomStream {
             atoms: self.atoms.iter().map(|atom: &Atom| -> Atom {
                 match atom {
                     Atom::Addressable(Address::Deferred(Spanned { node: label, span }), kind) => {
-                        if let Some((offset, _)) = label_definitions.get(&label.clone()) {
+                        if let Some(offset) = label_definitions.get(&label.clone()) {
                             Atom::Addressable(Address::Resolved(*offset), *kind)
                         } else {
                             Atom::Addressable(Address::Deferred(label.clone().spanning(*span)), *kind)
@@ -132,12 +255,14 @@ impl AtomStream {
                     x => x.clone(),
                 }
             }).collect::<Vec<Atom>>(),
+            spans: self.spans,
             offset: self.offset,
         }
     }
 
     pub fn extend(&mut self, other: AtomStream) {
         self.atoms.extend(other.atoms);
+        self.spans.extend(other.spans);
         self.offset = self.offset + other.offset;
     }
 
@@ -145,77 +270,189 @@ impl AtomStream {
         &self.atoms
     }
 
+    pub fn spans(&self) -> &Vec<Option<Span>> {
+        &self.spans
+    }
+
     pub fn offset(&self) -> Offset {
         self.offset
     }
 }
 
-pub(super) fn emit(
+pub struct Optimizations {
+    pub fold_constants: bool,
+    pub peephole: bool,
+    pub strip_dead_code: bool,
+    pub kept_labels: Vec<String>,
+}
+
+impl Optimizations {
+    /// Assembles the source as it is written.
+    pub fn none() -> Optimizations {
+        Optimizations {
+            fold_constants: false,
+            peephole: false,
+            strip_dead_code: false,
+            kept_labels: Vec::new(),
+        }
+    }
+}
+
+pub struct Rewrite {
+    pub rule: &'static str,
+    pub count: usize,
+    pub saved_bytes: usize,
+}
+
+fn record(rewrites: &mut Vec<Rewrite>, rule: &'static str, saved_bytes: usize) {
+    if let Some(rewrite) = rewrites.iter_mut().find(|rewrite| rewrite.rule == rule) {
+        rewrite.count += 1;
+        rewrite.saved_bytes += saved_bytes;
+    } else {
+        rewrites.push(Rewrite { rule, count: 1, saved_bytes });
+    }
+}
+
+#[derive(Clone)]
+pub struct Symbol {
+    pub label: String,
+    pub address: usize,
+    pub size: usize,
+    pub span: Span,
+}
+
+/// The bytes rendered from a single atom, or the position of a label
+/// definition, which renders no bytes.
+pub struct Fragment {
+    pub address: usize,
+    pub size: usize,
+    pub span: Option<Span>,
+    pub label: Option<String>,
+}
+
+/// A stack assertion comment, to be checked right before the instruction at
+/// its address is executed.
+pub struct StackAssertion {
+    pub address: usize,
+    pub pattern: StackPattern,
+    pub span: Span,
+}
+
+pub struct Assembly {
+    pub binary: Vec<u8>,
+    pub fragments: Vec<Fragment>,
+    pub entry_point: usize,
+    pub symbols: Vec<Symbol>,
+    pub stack_assertions: Vec<StackAssertion>,
+    pub rewrites: Vec<Rewrite>,
+    pub removed_blocks: Vec<RemovedBlock>,
+}
+
+pub fn emit(
     tokens: &[Spanned<Token>],
     yot_type: YotType,
     initial_stack_pointer: u64,
     exact_binary_size: Option<usize>,
-) -> Result<Vec<u8>, Vec<Error>> {
+    optimizations: &Optimizations,
+) -> Result<Assembly, Vec<Error>> {
     let mut atom_stream: AtomStream = AtomStream::new();
+    let entry_point: usize = yot_type as usize * 2;
 
     atom_stream.push_address_literal(initial_stack_pointer);
-    atom_stream.push_address_literal(yot_type as u64 * 2);
+    atom_stream.push_address_literal(entry_point as u64);
+
+    let mut nested_atom_stream = emit_tokens(tokens)?;
+    let mut removed_blocks: Vec<RemovedBlock> = Vec::new();
+    let mut rewrites: Vec<Rewrite> = Vec::new();
+
+    if optimizations.strip_dead_code {
+        let (stripped_atom_stream, stripped_blocks) = dead_code::eliminate(&nested_atom_stream, &optimizations.kept_labels, yot_type)?;
+        nested_atom_stream = stripped_atom_stream;
+        removed_blocks = stripped_blocks;
+    }
 
-    let nested_atom_stream = emit_tokens(tokens)?;
-    atom_stream.extend(nested_atom_stream.offseted(atom_stream.offset()));
+    if optimizations.fold_constants {
+        nested_atom_stream = folding::fold(&nested_atom_stream, &mut rewrites);
+    }
+
+    if optimizations.peephole {
+        nested_atom_stream = peephole::optimize(&nested_atom_stream, &mut rewrites);
+    }
+
+    atom_stream.extend(nested_atom_stream);
 
-    let mut binary: Vec<u8> = render(&atom_stream.atoms(), yot_type)?;
+    let label_definitions = atom_stream.label_definitions();
+    let symbols = atom_stream.symbols(yot_type);
+    let atom_stream = atom_stream.resolved(&label_definitions);
+
+    let Rendering { mut binary, fragments, stack_assertions } = render(&atom_stream, yot_type)?;
 
     if let Some(size) = exact_binary_size {
         pad(&mut binary, size).map_err(|err| vec![err])?;
     }
 
-    Ok(binary)
+    Ok(Assembly { binary, fragments, entry_point, symbols, stack_assertions, rewrites, removed_blocks })
 }
 
 fn emit_tokens(tokens: &[Spanned<Token>]) -> Result<AtomStream, Vec<Error>> {
     let mut errors: Vec<Error> = Vec::new();
     let mut atom_stream: AtomStream = AtomStream::new();
-    let mut label_definitions: HashMap<String, (Offset, Span)> = HashMap::new();
+    let mut label_definitions: HashMap<String, Span> = HashMap::new();
 
     for token in tokens.iter() {
         match token {
-            Spanned { node: Token::PrimitiveInstruction(instruction_kind), .. } => {
-                atom_stream.push_primitive_instruction(*instruction_kind);
+            Spanned { node: Token::PrimitiveInstruction(instruction_kind), span } => {
+                atom_stream.push_primitive_instruction(*instruction_kind, *span);
             }
             Spanned { node: Token::SubroutineJump(label), span } => {
                 atom_stream.push_subroutine_jump(label.clone(), *span);
             }
-            Spanned { node: Token::DataLiteral(byte_vector), .. } => {
-                atom_stream.push_data_literal(byte_vector.to_owned());
+            Spanned { node: Token::DataLiteral(byte_vector), span } => {
+                atom_stream.push_data_literal(byte_vector.to_owned(), *span);
             }
             Spanned { node: Token::LabelDefinition(label), span } => {
-                if let Some((_, previous_span)) = label_definitions.insert(label.to_owned(), (atom_stream.offset(), *span)) {
+                if let Some(previous_span) = label_definitions.insert(label.to_owned(), *span) {
                     errors.push(Error::LabelDefinedMoreThanOnce {
                         label: label.to_string(),
                         current_label_span: *span,
                         previously_defined_label_span: previous_span,
                     });
                 }
+                atom_stream.push_label_definition(label.to_owned(), *span);
             }
             Spanned { node: Token::LabelLiteral(label), span } => {
                 atom_stream.push_label_literal(label.to_owned(), *span);
             }
+            Spanned { node: Token::StackAssertion(pattern), span } => {
+                atom_stream.push_stack_assertion(pattern.clone(), *span);
+            }
+            // test cases are run by `test_runner` and assemble to nothing
+            Spanned { node: Token::TestCase(_), .. } => (),
         }
     }
 
     if errors.is_empty() {
-        Ok(atom_stream.resolved(&label_definitions))
+        Ok(atom_stream)
     } else {
         Err(errors)
     }
 }
 
-fn render(atoms: &[Atom], yot_type: YotType) -> Result<Vec<u8>, Vec<Error>> {
+/// The output of rendering an atom stream.
+struct Rendering {
+    binary: Vec<u8>,
+    fragments: Vec<Fragment>,
+    stack_assertions: Vec<StackAssertion>,
+}
+
+fn render(atom_stream: &AtomStream, yot_type: YotType) -> Result<Rendering, Vec<Error>> {
     let mut binary: Vec<u8> = Vec::new();
+    let mut fragments: Vec<Fragment> = Vec::new();
+    let mut stack_assertions: Vec<StackAssertion> = Vec::new();
     let mut errors: Vec<Error> = Vec::new();
 
-    for atom in atoms.iter() {
+    for (atom, span) in atom_stream.atoms().iter().zip(atom_stream.spans().iter()) {
+        let address = binary.len();
         match atom {
             Atom::Constant(constant_kind) => {
                 match constant_kind {
@@ -231,8 +468,8 @@ fn render(atoms: &[Atom], yot_type: YotType) -> Result<Vec<u8>, Vec<Error>> {
                     ConstantKind::AddressLiteral(address) => {
                         for i in 0..yot_type as usize {
                             let index = yot_type as usize - i - 1;
-                            let mask = 0xff << (index * 2);
-                            binary.push((address & mask >> (index * 2)) as u8);
+                            let mask = 0xff << (index * 8);
+                            binary.push(((address & mask) >> (index * 8)) as u8);
                         }
                     }
                 }
@@ -244,16 +481,16 @@ fn render(atoms: &[Atom], yot_type: YotType) -> Result<Vec<u8>, Vec<Error>> {
                         for i in 0..yot_type as usize {
                             binary.push(PUSH_OPCODE);
                             let index = yot_type as usize - i - 1;
-                            let mask = 0xff << (index * 2);
-                            binary.push((address & mask >> (index * 2)) as u8);
+                            let mask = 0xff << (index * 8);
+                            binary.push(((address & mask) >> (index * 8)) as u8);
                         }
                     }
                     AddressableKind::SubroutineJump => {
                         for i in 0..yot_type as usize {
                             binary.push(PUSH_OPCODE);
                             let index = yot_type as usize - i - 1;
-                            let mask = 0xff << (index * 2);
-                            binary.push((address & mask >> (index * 2)) as u8);
+                            let mask = 0xff << (index * 8);
+                            binary.push(((address & mask) >> (index * 8)) as u8);
                         }
                         binary.push(SUBROUTINE_JUMP_OPCODE);
                     }
@@ -265,11 +502,26 @@ fn render(atoms: &[Atom], yot_type: YotType) -> Result<Vec<u8>, Vec<Error>> {
                     span: *span,
                 });
             }
+            Atom::LabelDefinition(_) => (),
+            Atom::StackAssertion(pattern) => {
+                if let Some(span) = span {
+                    stack_assertions.push(StackAssertion { address, pattern: pattern.clone(), span: *span });
+                }
+            }
         };
+        fragments.push(Fragment {
+            address,
+            size: binary.len() - address,
+            span: *span,
+            label: match atom {
+                Atom::LabelDefinition(Spanned { node: label, .. }) => Some(label.clone()),
+                _ => None,
+            },
+        });
     }
 
     if errors.is_empty() {
-        Ok(binary)
+        Ok(Rendering { binary, fragments, stack_assertions })
     } else {
         Err(errors)
     }
@@ -288,3 +540,52 @@ fn pad(binary: &mut Vec<u8>, size: usize) -> Result<(), Error> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble(source: &str, yot_type: YotType, initial_stack_pointer: u64) -> Vec<u8> {
+        let tokens = super::super::tokenizer::tokenize(source, 0).unwrap();
+        emit(&tokens, yot_type, initial_stack_pointer, None, &Optimizations::none()).unwrap().binary
+    }
+
+    fn big_endian(address: u64, yot_type: YotType) -> Vec<u8> {
+        address.to_be_bytes()[8 - yot_type as usize..].to_vec()
+    }
+
+    /// The operands of the pushes of an address starting at `start`.
+    fn pushed_address(binary: &[u8], start: usize, yot_type: YotType) -> Vec<u8> {
+        (0..yot_type as usize).map(|i| {
+            assert_eq!(binary[start + i * 2], PUSH_OPCODE);
+            binary[start + i * 2 + 1]
+        }).collect()
+    }
+
+    #[test]
+    fn header_addresses_are_big_endian() {
+        for yot_type in [YotType::Y16, YotType::Y32, YotType::Y64] {
+            let stack_pointer = 0x0807060504030201 & (u64::MAX >> (64 - yot_type as u64 * 8));
+            let binary = assemble("brk", yot_type, stack_pointer);
+            let address_size = yot_type as usize;
+            assert_eq!(binary[..address_size], big_endian(stack_pointer, yot_type)[..]);
+            assert_eq!(binary[address_size..address_size * 2], big_endian(address_size as u64 * 2, yot_type)[..]);
+        }
+    }
+
+    #[test]
+    fn label_addresses_are_big_endian() {
+        // enough code to push the label past the first 256 bytes
+        let source = format!("@end jmp\nend\n{}:end\nbrk\n", "nop\n".repeat(0x1234));
+        for yot_type in [YotType::Y16, YotType::Y32, YotType::Y64] {
+            let binary = assemble(&source, yot_type, 0);
+            let address_size = yot_type as usize;
+            let end = binary.len() as u64 - 1;
+            let label_literal = address_size * 2;
+            let subroutine_jump = label_literal + address_size * 2 + 1;
+            assert_eq!(pushed_address(&binary, label_literal, yot_type), big_endian(end, yot_type));
+            assert_eq!(pushed_address(&binary, subroutine_jump, yot_type), big_endian(end, yot_type));
+            assert_eq!(binary[subroutine_jump + address_size * 2], SUBROUTINE_JUMP_OPCODE);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use super::{Atom, AtomStream, ConstantKind, InstructionKind, Rewrite, record};
+
+struct Rule {
+    name: &'static str,
+    apply: fn(&mut AtomStream) -> bool,
+}
+
+const LITERAL_MERGE: &str = "literal-merge";
+
+fn rules(instruction_kind: &InstructionKind) -> &'static [Rule] {
+    match instruction_kind {
+        InstructionKind::NoOperation => &[
+            Rule { name: "nop", apply: remove_instruction },
+        ],
+        InstructionKind::Drop => &[
+            Rule { name: "literal-drop", apply: literal_drop },
+            Rule { name: "duplicate-drop", apply: duplicate_drop },
+        ],
+        InstructionKind::Switch => &[
+            Rule { name: "switch-zero", apply: zero_operand },
+            Rule { name: "double-switch", apply: double_switch },
+        ],
+        InstructionKind::Roll => &[
+            Rule { name: "roll-zero", apply: zero_operand },
+        ],
+        InstructionKind::Insert => &[
+            Rule { name: "insert-zero", apply: zero_operand },
+        ],
+        _ => &[],
+    }
+}
+
+/// Rewrites the atom stream in a single forward pass. Every instruction is
+/// matched against the rules of its kind with the already optimized atoms
+/// preceding it, so that rewrites can cascade (`#01 #02 drp drp` vanishes
+/// completely). Label definitions are atoms too, so no pattern ever spans
+/// across one.
+pub(super) fn optimize(atom_stream: &AtomStream, rewrites: &mut Vec<Rewrite>) -> AtomStream {
+    let mut optimized: AtomStream = AtomStream::new();
+
+    for (atom, span) in atom_stream.atoms().iter().zip(atom_stream.spans().iter()) {
+        match atom {
+            Atom::Constant(ConstantKind::PrimitiveInstruction(instruction_kind)) => {
+                let size_before = optimized.offset().constant + atom.size().constant;
+                match rules(instruction_kind).iter().find(|rule| (rule.apply)(&mut optimized)) {
+                    Some(rule) => record(rewrites, rule.name, size_before - optimized.offset().constant),
+                    None => optimized.push(atom.clone(), *span),
+                }
+            }
+            Atom::Constant(ConstantKind::DataLiteral(data)) => {
+                if let Some(Atom::Constant(ConstantKind::DataLiteral(_))) = optimized.atoms().last() {
+                    for byte in data.iter() {
+                        optimized.push_literal_byte(*byte, *span);
+                    }
+                    record(rewrites, LITERAL_MERGE, 0);
+                } else {
+                    optimized.push(atom.clone(), *span);
+                }
+            }
+            _ => optimized.push(atom.clone(), *span),
+        }
+    }
+
+    optimized
+}
+
+fn is_instruction(atom_stream: &AtomStream, index_from_end: usize, kind: fn(&InstructionKind) -> bool) -> bool {
+    let atoms = atom_stream.atoms();
+    matches!(
+        atoms.len().checked_sub(index_from_end + 1).and_then(|index| atoms.get(index)),
+        Some(Atom::Constant(ConstantKind::PrimitiveInstruction(instruction_kind))) if kind(instruction_kind)
+    )
+}
+
+/// `nop` →
+fn remove_instruction(_: &mut AtomStream) -> bool {
+    true
+}
+
+/// `#xx drp` →
+fn literal_drop(atom_stream: &mut AtomStream) -> bool {
+    if atom_stream.last_literal_byte(0).is_none() {
+        return false;
+    }
+    atom_stream.pop_literal_byte();
+    true
+}
+
+/// `#00 pck drp` →
+fn duplicate_drop(atom_stream: &mut AtomStream) -> bool {
+    if !is_instruction(atom_stream, 0, |kind| matches!(kind, InstructionKind::Pick)) || atom_stream.last_literal_byte(1) != Some(0x00) {
+        return false;
+    }
+    atom_stream.pop();
+    atom_stream.pop_literal_byte();
+    true
+}
+
+/// `#00 swc`, `#00 rol`, `#00 ins` →
+fn zero_operand(atom_stream: &mut AtomStream) -> bool {
+    if atom_stream.last_literal_byte(0) != Some(0x00) {
+        return false;
+    }
+    atom_stream.pop_literal_byte();
+    true
+}
+
+/// `#xx swc #xx swc` →
+fn double_switch(atom_stream: &mut AtomStream) -> bool {
+    let depth = match atom_stream.atoms().last() {
+        Some(Atom::Constant(ConstantKind::DataLiteral(data))) if data.len() == 1 => data[0],
+        _ => return false,
+    };
+    if !is_instruction(atom_stream, 1, |kind| matches!(kind, InstructionKind::Switch)) || atom_stream.last_literal_byte(2) != Some(depth) {
+        return false;
+    }
+    atom_stream.pop();
+    atom_stream.pop();
+    atom_stream.pop_literal_byte();
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::super::tokenizer::tokenize;
+    use super::super::super::YotType;
+    use super::super::{Optimizations, emit};
+
+    fn assemble(source: &str, peephole: bool) -> super::super::Assembly {
+        let tokens = tokenize(source, 0).unwrap();
+        emit(&tokens, YotType::Y16, 0x1000, None, &Optimizations { peephole, ..Optimizations::none() }).unwrap()
+    }
+
+    /// Checks that `source` is optimized into the same binary as `expected`
+    /// and returns the rules applied along with their counts and savings.
+    fn optimize(source: &str, expected: &str) -> Vec<(&'static str, usize, usize)> {
+        let optimized = assemble(source, true);
+        assert_eq!(optimized.binary, assemble(expected, false).binary, "{}", source);
+        optimized.rewrites.iter().map(|rewrite| (rewrite.rule, rewrite.count, rewrite.saved_bytes)).collect()
+    }
+
+    #[test]
+    fn removes_no_operations() {
+        assert_eq!(optimize("nop add nop", "add"), [("nop", 2, 2)]);
+    }
+
+    #[test]
+    fn removes_dropped_literals() {
+        assert_eq!(optimize("#2a drp add", "add"), [("literal-drop", 1, 3)]);
+        assert_eq!(optimize("#01 #02 drp", "#01"), [("literal-merge", 1, 0), ("literal-drop", 1, 3)]);
+        assert_eq!(optimize("add drp", "add drp"), []);
+    }
+
+    #[test]
+    fn removes_dropped_duplicates() {
+        assert_eq!(optimize("add #00 pck drp", "add"), [("duplicate-drop", 1, 4)]);
+        assert_eq!(optimize("add #01 pck drp", "add #01 pck drp"), []);
+    }
+
+    #[test]
+    fn removes_zero_depth_operands() {
+        assert_eq!(optimize("add #00 swc", "add"), [("switch-zero", 1, 3)]);
+        assert_eq!(optimize("add #00 rol", "add"), [("roll-zero", 1, 3)]);
+        assert_eq!(optimize("add #00 ins", "add"), [("insert-zero", 1, 3)]);
+        assert_eq!(optimize("add #01 rol", "add #01 rol"), []);
+    }
+
+    #[test]
+    fn removes_double_switches() {
+        assert_eq!(optimize("add #02 swc #02 swc", "add"), [("double-switch", 1, 6)]);
+        assert_eq!(optimize("add #01 swc #02 swc", "add #01 swc #02 swc"), []);
+    }
+
+    #[test]
+    fn merges_adjacent_literals() {
+        assert_eq!(optimize("#01 #02 add", "#01 #02 add"), [("literal-merge", 1, 0)]);
+    }
+
+    #[test]
+    fn cascades_rewrites() {
+        let rewrites = optimize("#01 #02 drp drp add", "add");
+        assert_eq!(rewrites, [("literal-merge", 1, 0), ("literal-drop", 2, 6)]);
+    }
+
+    #[test]
+    fn keeps_patterns_spanning_label_definitions() {
+        let source = "@end jmp #2a :end drp #00 :zero swc";
+        assert_eq!(optimize(source, source), []);
+    }
+}
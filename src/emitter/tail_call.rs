@@ -0,0 +1,237 @@
+use super::{InstructionKind, Span, Spanned, Spanning, Token, YotType, evaluate_pure};
+use std::iter;
+
+pub struct TailCall {
+    pub label: String,
+    pub span: Span,
+}
+
+/// Rewrites subroutine jumps that are immediately followed by a `jmp` into
+/// plain jumps to the callee, e.g. `mul jmp` into `@mul jmp`.
+///
+/// Entering the callee with a plain jump leaves the caller's own return
+/// address, or whatever the caller's `jmp` would have popped, right where the
+/// callee expects its return address. That is only the same thing if the
+/// callee never looks below its return address, so a call is only rewritten
+/// if `returns_untouched` proves that for the callee. A label definition
+/// between the two tokens makes the `jmp` a jump target on its own, so such
+/// pairs are left alone.
+pub fn optimize(tokens: &[Spanned<Token>], yot_type: YotType) -> (Vec<Spanned<Token>>, Vec<TailCall>) {
+    let mut optimized: Vec<Spanned<Token>> = Vec::with_capacity(tokens.len());
+    let mut tail_calls: Vec<TailCall> = Vec::new();
+
+    for (index, token) in tokens.iter().enumerate() {
+        match (token, tokens.get(index + 1)) {
+            (
+                Spanned { node: Token::SubroutineJump(label), span },
+                Some(Spanned { node: Token::PrimitiveInstruction(InstructionKind::Jump), span: jump_span }),
+            ) if returns_untouched(tokens, label, yot_type) => {
+                tail_calls.push(TailCall {
+                    label: label.clone(),
+                    span: Span::combine(span, jump_span),
+                });
+                optimized.push(Token::LabelLiteral(label.clone()).spanning(*span));
+            }
+            (token, _) => optimized.push(token.clone()),
+        }
+    }
+
+    (optimized, tail_calls)
+}
+
+/// Checks that the subroutine at `label` works on nothing but the stack cells
+/// it pushes itself and returns by popping exactly the return address it was
+/// entered with, so that it behaves the same when entered without one.
+///
+/// The check follows the straight-line code from the label and gives up on
+/// anything it cannot follow: branches, subroutine jumps, `brk`, interrupt
+/// control, and depth operands of stack instructions that aren't pushed by
+/// data literals within the subroutine. Memory accesses are assumed not to
+/// reach into the stack.
+fn returns_untouched(tokens: &[Spanned<Token>], label: &str, yot_type: YotType) -> bool {
+    let start = match tokens.iter().position(|token| {
+        matches!(&token.node, Token::LabelDefinition(definition) if definition == label)
+    }) {
+        Some(start) => start + 1,
+        None => return false,
+    };
+
+    // the cells pushed since the subroutine was entered, with their values
+    // where they are known
+    let mut stack: Vec<Option<u8>> = Vec::new();
+    for token in tokens[start..].iter() {
+        match &token.node {
+            Token::DataLiteral(data) => stack.extend(data.iter().copied().map(Some)),
+            Token::LabelLiteral(_) => stack.extend(iter::repeat_n(None, yot_type as usize)),
+            Token::PrimitiveInstruction(InstructionKind::Jump) => return stack.is_empty(),
+            Token::PrimitiveInstruction(instruction_kind) => {
+                if execute(&mut stack, instruction_kind, yot_type as usize).is_none() {
+                    return false;
+                }
+            }
+            Token::SubroutineJump(_) => return false,
+            Token::LabelDefinition(_) | Token::StackAssertion(_) | Token::TestCase(_) => (),
+        }
+    }
+    false
+}
+
+/// Pops a depth operand that has to address one of the remaining cells.
+fn pop_depth(stack: &mut Vec<Option<u8>>) -> Option<usize> {
+    let depth = stack.pop()?? as usize;
+    if depth < stack.len() {
+        Some(depth)
+    } else {
+        None
+    }
+}
+
+fn pop_cells(stack: &mut Vec<Option<u8>>, count: usize) -> Option<()> {
+    stack.truncate(stack.len().checked_sub(count)?);
+    Some(())
+}
+
+/// Applies an instruction to the cells pushed by the subroutine, or returns
+/// `None` if it would touch any other cell or leave the straight-line code.
+fn execute(stack: &mut Vec<Option<u8>>, instruction_kind: &InstructionKind, address_size: usize) -> Option<()> {
+    match instruction_kind {
+        InstructionKind::NoOperation => (),
+        InstructionKind::Drop => pop_cells(stack, 1)?,
+        InstructionKind::Switch => {
+            let depth = pop_depth(stack)?;
+            let top = stack.len() - 1;
+            stack.swap(top, top - depth);
+        }
+        InstructionKind::Pick => {
+            let depth = pop_depth(stack)?;
+            stack.push(stack[stack.len() - 1 - depth]);
+        }
+        InstructionKind::Paste => {
+            let depth = stack.pop()?? as usize;
+            let byte = stack.pop()?;
+            let cell = stack.len().checked_sub(depth + 1)?;
+            stack[cell] = byte;
+        }
+        InstructionKind::Roll => {
+            let depth = pop_depth(stack)?;
+            let byte = stack.remove(stack.len() - 1 - depth);
+            stack.push(byte);
+        }
+        InstructionKind::Insert => {
+            let depth = pop_depth(stack)?;
+            let cell = stack.len() - 1 - depth;
+            let byte = stack.pop()?;
+            stack.insert(cell, byte);
+        }
+        InstructionKind::FetchMemory => {
+            pop_cells(stack, address_size)?;
+            stack.push(None);
+        }
+        InstructionKind::StoreMemory => pop_cells(stack, address_size + 1)?,
+        InstructionKind::Add
+        | InstructionKind::Subtract
+        | InstructionKind::AddWithCarry
+        | InstructionKind::SubtractWithBorrow
+        | InstructionKind::And
+        | InstructionKind::InclusiveOr
+        | InstructionKind::ExclusiveOr
+        | InstructionKind::ShiftLeft
+        | InstructionKind::ShiftRight
+        | InstructionKind::Equal
+        | InstructionKind::NotEqual => {
+            let b = stack.pop()?;
+            let a = stack.pop()?;
+            stack.push(match (a, b) {
+                (Some(a), Some(b)) => evaluate_pure(instruction_kind, a, b),
+                _ => None,
+            });
+        }
+        InstructionKind::Break
+        | InstructionKind::Jump
+        | InstructionKind::Branch
+        | InstructionKind::SetInterruptFlag
+        | InstructionKind::SetInterruptAddress => return None,
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::emulator::Emulator;
+    use super::super::super::tokenizer::tokenize;
+    use super::super::{Assembly, Optimizations, emit};
+
+    fn assemble(tokens: &[Spanned<Token>], yot_type: YotType) -> Assembly {
+        emit(tokens, yot_type, 0x80, None, &Optimizations::none()).unwrap()
+    }
+
+    /// The final stack and the byte at the `cell` label after running the
+    /// program.
+    fn run(assembly: &Assembly, yot_type: YotType) -> (Vec<u8>, u8) {
+        let mut emulator = Emulator::new(&assembly.binary, yot_type).unwrap();
+        emulator.run(Some(1000)).unwrap();
+        let cell = assembly.symbols.iter().find(|symbol| symbol.label == "cell").unwrap();
+        (emulator.stack(), emulator.memory.read(cell.address as u64))
+    }
+
+    /// Runs the program before and after the rewrite, checks that both end
+    /// the same way and returns the number of rewritten calls.
+    fn assert_equivalent(source: &str, yot_type: YotType) -> usize {
+        let tokens = tokenize(source, 0).unwrap();
+        let (optimized, tail_calls) = optimize(&tokens, yot_type);
+        assert_eq!(run(&assemble(&tokens, yot_type), yot_type), run(&assemble(&optimized, yot_type), yot_type));
+        tail_calls.len()
+    }
+
+    const YOT_TYPES: [YotType; 4] = [YotType::Y8, YotType::Y16, YotType::Y32, YotType::Y64];
+
+    #[test]
+    fn rewrites_calls_to_subroutines_without_stack_arguments() {
+        let source = "
+            #01 #02 caller add brk
+            :caller
+            store jmp
+            :store
+            #2a #00 pck #01 swc @cell stm drp jmp
+            :cell
+            nop
+        ";
+        for yot_type in YOT_TYPES {
+            assert_eq!(assert_equivalent(source, yot_type), 1);
+        }
+    }
+
+    #[test]
+    fn keeps_calls_to_subroutines_taking_stack_arguments() {
+        for yot_type in YOT_TYPES {
+            // the argument sits right below the return address
+            let source = format!("
+                caller brk
+                :caller
+                #07 consume jmp
+                :consume
+                #{:02x} rol @cell stm jmp
+                :cell
+                nop
+            ", yot_type as usize);
+            assert_eq!(assert_equivalent(&source, yot_type), 0);
+        }
+    }
+
+    #[test]
+    fn keeps_calls_to_subroutines_it_cannot_follow() {
+        let source = "
+            caller brk
+            :caller
+            branch jmp
+            :branch
+            #00 @skip bnc
+            :skip
+            jmp
+            :cell
+            nop
+        ";
+        assert_eq!(assert_equivalent(source, YotType::Y8), 0);
+    }
+}
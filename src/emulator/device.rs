@@ -0,0 +1,319 @@
+use std::io::{self, BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use super::Error;
+
+/// A device mapped into the address space, which `fcm` reads from and `stm`
+/// writes to instead of memory. Instructions and the stack are never fetched
+/// from devices.
+pub trait Device {
+    /// The number of addresses the device takes up.
+    fn size(&self) -> u64;
+
+    /// Reads the byte at the offset into the device. `steps` is the number of
+    /// instructions executed so far.
+    fn read(&mut self, offset: u64, steps: u64) -> io::Result<u8>;
+
+    fn write(&mut self, offset: u64, byte: u8) -> io::Result<()>;
+
+    /// Whether the device asks for an interrupt.
+    fn interrupt_pending(&mut self) -> io::Result<bool> {
+        Ok(false)
+    }
+}
+
+struct Mapping {
+    start: u64,
+    device: Box<dyn Device>,
+}
+
+/// The devices mapped into the address space, none by default.
+pub struct Devices {
+    mappings: Vec<Mapping>,
+    /// The last address of the address space.
+    last_address: u64,
+}
+
+impl Devices {
+    pub fn new(last_address: u64) -> Devices {
+        Devices {
+            mappings: Vec::new(),
+            last_address,
+        }
+    }
+
+    /// Maps the device at the address, unless it reaches past the end of the
+    /// address space or overlaps a device that is already mapped.
+    pub fn map(&mut self, start: u64, device: Box<dyn Device>) -> Result<(), Error> {
+        let size = device.size();
+        if start > self.last_address || size.saturating_sub(1) > self.last_address - start {
+            return Err(Error::DeviceOutsideAddressSpace { address: start, size, last_address: self.last_address });
+        }
+        let end = start.saturating_add(size);
+        for mapping in self.mappings.iter() {
+            let mapped_end = mapping.start.saturating_add(mapping.device.size());
+            if start < mapped_end && mapping.start < end {
+                return Err(Error::DevicesOverlap { address: start.max(mapping.start) });
+            }
+        }
+        self.mappings.push(Mapping { start, device });
+        Ok(())
+    }
+
+    /// Whether any device asks for an interrupt.
+    pub fn interrupt_pending(&mut self) -> io::Result<bool> {
+        for mapping in self.mappings.iter_mut() {
+            if mapping.device.interrupt_pending()? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// The device mapped at the address and the offset of the address into it.
+    pub fn get(&mut self, address: u64) -> Option<(&mut (dyn Device + 'static), u64)> {
+        self.mappings.iter_mut()
+            .find(|mapping| address >= mapping.start && address - mapping.start < mapping.device.size())
+            .map(|mapping| (mapping.device.as_mut(), address - mapping.start))
+    }
+}
+
+/// A single byte port that writes every byte stored to it to the output,
+/// e.g. the standard output. Reads return zero.
+pub struct ConsoleOutput {
+    output: Box<dyn Write>,
+}
+
+impl ConsoleOutput {
+    pub fn new(output: Box<dyn Write>) -> ConsoleOutput {
+        ConsoleOutput { output }
+    }
+}
+
+impl Device for ConsoleOutput {
+    fn size(&self) -> u64 {
+        1
+    }
+
+    fn read(&mut self, _: u64, _: u64) -> io::Result<u8> {
+        Ok(0)
+    }
+
+    fn write(&mut self, _: u64, byte: u8) -> io::Result<()> {
+        self.output.write_all(&[byte])?;
+        self.output.flush()
+    }
+}
+
+/// Two ports reading from the input, e.g. the standard input. Reading the
+/// first one takes the next byte of the input, or zero at its end. Reading
+/// the second one returns 1 while there is input left and 0 after that,
+/// without taking anything. Both wait for the next byte if it hasn't arrived
+/// yet. Writes are ignored. The device asks for an interrupt once the next
+/// byte has arrived, without waiting for it.
+pub struct ConsoleInput {
+    /// The bytes of the input, read on a thread of their own so that asking
+    /// for an interrupt never waits for them.
+    bytes: Receiver<io::Result<u8>>,
+    next: Option<Option<u8>>,
+}
+
+impl ConsoleInput {
+    pub fn new(input: Box<dyn Read + Send>) -> ConsoleInput {
+        let (sender, bytes) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in BufReader::new(input).bytes() {
+                let failed = byte.is_err();
+                if sender.send(byte).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        ConsoleInput { bytes, next: None }
+    }
+
+    /// The next byte, or `None` at the end of the input, waiting for it if
+    /// `wait`. Returns `Ok(None)` without waiting if it hasn't arrived yet.
+    fn peek(&mut self, wait: bool) -> io::Result<Option<Option<u8>>> {
+        if self.next.is_none() {
+            let received = if wait {
+                self.bytes.recv().map_err(|_| TryRecvError::Disconnected)
+            } else {
+                self.bytes.try_recv()
+            };
+            self.next = match received {
+                Ok(byte) => Some(Some(byte?)),
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => Some(None),
+            };
+        }
+        Ok(self.next)
+    }
+}
+
+impl Device for ConsoleInput {
+    fn size(&self) -> u64 {
+        2
+    }
+
+    fn read(&mut self, offset: u64, _: u64) -> io::Result<u8> {
+        let next = self.peek(true)?.flatten();
+        if offset == 0 {
+            // the end of the input is sticky, so it is not taken
+            if next.is_some() {
+                self.next = None;
+            }
+            Ok(next.unwrap_or(0))
+        } else {
+            Ok(next.is_some() as u8)
+        }
+    }
+
+    fn write(&mut self, _: u64, _: u8) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn interrupt_pending(&mut self) -> io::Result<bool> {
+        Ok(self.peek(false)?.flatten().is_some())
+    }
+}
+
+/// Eight ports holding the number of instructions executed so far, from the
+/// most significant byte down. Reading the first one latches the count, so
+/// that reading the ports in order gives a consistent count. Writes are
+/// ignored.
+#[derive(Default)]
+pub struct CycleCounter {
+    latched: u64,
+}
+
+impl CycleCounter {
+    pub fn new() -> CycleCounter {
+        CycleCounter::default()
+    }
+}
+
+impl Device for CycleCounter {
+    fn size(&self) -> u64 {
+        8
+    }
+
+    fn read(&mut self, offset: u64, steps: u64) -> io::Result<u8> {
+        if offset == 0 {
+            self.latched = steps;
+        }
+        Ok((self.latched >> ((7 - offset) * 8)) as u8)
+    }
+
+    fn write(&mut self, _: u64, _: u8) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Emulator, YotType};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::mpsc::Sender;
+
+    /// An output that can still be looked at once the device owns it.
+    #[derive(Clone, Default)]
+    struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// An input whose bytes arrive only once they are sent.
+    struct PipeInput(Receiver<u8>);
+
+    impl Read for PipeInput {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            match self.0.recv() {
+                Ok(byte) if !buffer.is_empty() => {
+                    buffer[0] = byte;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    fn pipe() -> (Sender<u8>, ConsoleInput) {
+        let (sender, receiver) = mpsc::channel();
+        (sender, ConsoleInput::new(Box::new(PipeInput(receiver))))
+    }
+
+    #[test]
+    fn writes_stored_bytes_to_the_console() {
+        // yot-8 with the stack at 0x80, storing to the port at 0xf0
+        let mut emulator = Emulator::new(&[0x80, 0x02, 0x20, 0x68, 0x20, 0xf0, 0x61, 0x20, 0x69, 0x20, 0xf0, 0x61, 0x00], YotType::Y8).unwrap();
+        let output = SharedOutput::default();
+        emulator.devices.map(0xf0, Box::new(ConsoleOutput::new(Box::new(output.clone())))).unwrap();
+        emulator.run(Some(10)).unwrap();
+        assert_eq!(*output.0.borrow(), b"hi");
+        assert_eq!(emulator.memory.read(0xf0), 0x00);
+    }
+
+    #[test]
+    fn reads_console_input_until_its_end() {
+        let (sender, mut input) = pipe();
+        sender.send(b'h').unwrap();
+        sender.send(b'i').unwrap();
+        drop(sender);
+        assert_eq!(input.read(1, 0).unwrap(), 1);
+        assert_eq!(input.read(1, 0).unwrap(), 1);
+        assert_eq!(input.read(0, 0).unwrap(), b'h');
+        assert_eq!(input.read(0, 0).unwrap(), b'i');
+        assert_eq!(input.read(1, 0).unwrap(), 0);
+        // the end of the input stays
+        assert_eq!(input.read(0, 0).unwrap(), 0);
+        assert_eq!(input.read(1, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn asks_for_interrupts_without_waiting_for_input() {
+        let (sender, mut input) = pipe();
+        assert!(!input.interrupt_pending().unwrap());
+        sender.send(b'h').unwrap();
+        // the byte arrives on a thread of its own
+        while !input.interrupt_pending().unwrap() {
+            thread::yield_now();
+        }
+        assert_eq!(input.read(0, 0).unwrap(), b'h');
+        assert!(!input.interrupt_pending().unwrap());
+        drop(sender);
+        assert_eq!(input.read(1, 0).unwrap(), 0);
+        assert!(!input.interrupt_pending().unwrap());
+    }
+
+    #[test]
+    fn latches_the_cycle_count_when_reading_the_first_port() {
+        let mut counter = CycleCounter::new();
+        assert_eq!(counter.read(0, 0x0102030405060708).unwrap(), 0x01);
+        // later reads of the other ports give the latched count
+        assert_eq!(counter.read(6, 0xffff).unwrap(), 0x07);
+        assert_eq!(counter.read(7, 0xffff).unwrap(), 0x08);
+        assert_eq!(counter.read(0, 0x0a0b).unwrap(), 0x00);
+        assert_eq!(counter.read(6, 0xffff).unwrap(), 0x0a);
+        assert_eq!(counter.read(7, 0xffff).unwrap(), 0x0b);
+    }
+
+    #[test]
+    fn reads_the_cycle_count_through_memory() {
+        // yot-8: #f8 fcm #ff fcm brk, with the counter at 0xf8
+        let mut emulator = Emulator::new(&[0x80, 0x02, 0x20, 0xf8, 0x60, 0x20, 0xff, 0x60, 0x00], YotType::Y8).unwrap();
+        emulator.devices.map(0xf8, Box::new(CycleCounter::new())).unwrap();
+        emulator.run(Some(10)).unwrap();
+        // latched by the first `fcm`, the second instruction executed
+        assert_eq!(emulator.stack(), [0x00, 0x02]);
+    }
+}
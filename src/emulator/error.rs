@@ -0,0 +1,53 @@
+use std::fmt;
+use crate::reporter::{Diagnostic, Report, Reporter};
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    HeaderMissing,
+    OpcodeUnknown { address: u64, opcode: u8 },
+    StackUnderflow { address: u64 },
+    StepLimitReached { steps: u64 },
+    DeviceFailed { address: u64, message: String },
+    DevicesOverlap { address: u64 },
+    DeviceOutsideAddressSpace { address: u64, size: u64, last_address: u64 },
+}
+
+impl Report for Error {
+    fn report(&self, r: &Reporter) {
+        r.write(Diagnostic {
+            message: self.to_string(),
+            labels: vec![],
+        });
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Error::HeaderMissing => write!(f,
+                "the binary is too short to hold a header",
+            ),
+            Error::OpcodeUnknown { address, opcode } => write!(f,
+                "unknown opcode {:#04x} at address {:#x}", opcode, address,
+            ),
+            Error::StackUnderflow { address } => write!(f,
+                "stack underflow at address {:#x}", address,
+            ),
+            Error::StepLimitReached { steps } => write!(f,
+                "stopped after {} instruction(s) without reaching `brk`", steps,
+            ),
+            Error::DeviceFailed { address, message } => write!(f,
+                "device access failed at address {:#x}: {}", address, message,
+            ),
+            Error::DevicesOverlap { address } => write!(f,
+                "devices overlap at address {:#x}", address,
+            ),
+            Error::DeviceOutsideAddressSpace { address, size, last_address } => write!(f,
+                "device of {} address(es) at {:#x} does not fit in the address space ending at {:#x}",
+                size, address, last_address,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
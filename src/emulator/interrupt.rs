@@ -0,0 +1,43 @@
+use std::collections::BTreeSet;
+
+/// What raises interrupts. An interrupt that is raised stays pending until
+/// it is taken.
+#[derive(Default)]
+pub struct Interrupts {
+    /// The instruction counts after which to raise an interrupt.
+    scheduled: BTreeSet<u64>,
+    /// Raise an interrupt after every so many instructions.
+    period: Option<u64>,
+    /// Raise an interrupt whenever a device asks for one.
+    pub devices: bool,
+    pub pending: bool,
+}
+
+impl Interrupts {
+    pub fn new() -> Interrupts {
+        Interrupts::default()
+    }
+
+    /// Raises an interrupt once the given number of instructions has been
+    /// executed.
+    pub fn schedule(&mut self, steps: u64) {
+        self.scheduled.insert(steps);
+    }
+
+    /// Raises an interrupt every time the given number of instructions has
+    /// been executed.
+    pub fn every(&mut self, period: u64) {
+        self.period = Some(period);
+    }
+
+    /// Raises the interrupts due after the given number of instructions.
+    pub(super) fn tick(&mut self, steps: u64) {
+        while self.scheduled.first().is_some_and(|scheduled| *scheduled <= steps) {
+            self.scheduled.pop_first();
+            self.pending = true;
+        }
+        if self.period.is_some_and(|period| period > 0 && steps.is_multiple_of(period)) {
+            self.pending = true;
+        }
+    }
+}
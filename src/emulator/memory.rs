@@ -0,0 +1,64 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use super::YotType;
+
+const PAGE_SIZE: usize = 0x100;
+const BYTES_PER_ROW: usize = 16;
+
+/// The address space of a Yot type. Memory is allocated in pages on the
+/// first write, so even the address space of `yot-64` is cheap to emulate,
+/// and reads from unallocated pages return zero. Addresses wrap around at
+/// the width of the Yot type.
+pub struct Memory {
+    yot_type: YotType,
+    pages: BTreeMap<u64, Box<[u8; PAGE_SIZE]>>,
+}
+
+impl Memory {
+    pub fn new(yot_type: YotType) -> Memory {
+        Memory {
+            yot_type,
+            pages: BTreeMap::new(),
+        }
+    }
+
+    /// Wraps the address around at the width of the Yot type.
+    pub fn wrap(&self, address: u64) -> u64 {
+        match self.yot_type {
+            YotType::Y64 => address,
+            yot_type => address & ((1 << (yot_type as u64 * 8)) - 1),
+        }
+    }
+
+    pub fn read(&self, address: u64) -> u8 {
+        let address = self.wrap(address);
+        self.pages
+            .get(&(address / PAGE_SIZE as u64))
+            .map(|page| page[(address % PAGE_SIZE as u64) as usize])
+            .unwrap_or(0)
+    }
+
+    pub fn write(&mut self, address: u64, byte: u8) {
+        let address = self.wrap(address);
+        let page = self.pages
+            .entry(address / PAGE_SIZE as u64)
+            .or_insert_with(|| Box::new([0; PAGE_SIZE]));
+        page[(address % PAGE_SIZE as u64) as usize] = byte;
+    }
+
+    /// Renders every row of allocated memory that holds a nonzero byte.
+    pub fn dump(&self) -> String {
+        let mut output: String = String::new();
+        for (index, page) in self.pages.iter() {
+            for (row, bytes) in page.chunks(BYTES_PER_ROW).enumerate() {
+                if bytes.iter().all(|byte| *byte == 0) {
+                    continue;
+                }
+                let address = index * PAGE_SIZE as u64 + (row * BYTES_PER_ROW) as u64;
+                let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+                let _ = writeln!(output, "{:0width$x}  {}", address, bytes.join(" "), width = self.yot_type as usize * 2);
+            }
+        }
+        output
+    }
+}
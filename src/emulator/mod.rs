@@ -0,0 +1,625 @@
+pub use device::{ConsoleInput, ConsoleOutput, CycleCounter, Device, Devices};
+pub use error::Error;
+pub use interrupt::Interrupts;
+pub use memory::Memory;
+use super::{InstructionKind, PUSH_OPCODE, SUBROUTINE_JUMP_OPCODE, YotType, evaluate_pure, get_instruction_kind_by_opcode, read_address};
+
+mod device;
+mod error;
+mod interrupt;
+mod memory;
+
+/// What a single step did to the flow of the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Continued,
+    Halted,
+    /// The instruction was executed and then an interrupt was taken, which
+    /// returns to the given address.
+    Interrupted { return_address: u64 },
+    /// The instruction returned from an interrupt handler to the address the
+    /// interrupt was taken at.
+    Returned { return_address: u64 },
+}
+
+/// Executes Yot binaries as laid out by `emitter::emit`: the binary is loaded
+/// at address 0 and execution starts at the entry point with the stack
+/// pointer from its header.
+///
+/// The data stack lives in memory and grows upwards: a push writes a byte at
+/// the stack pointer and increments it. Stack cells are single bytes on every
+/// Yot type, so an address takes up as many cells as the Yot type has bytes,
+/// pushed from the most significant byte down. Depth 0 is the top of the
+/// stack.
+///
+/// Interrupts are taken between instructions while the interrupt flag is
+/// set. Taking one is like a subroutine jump to the interrupt address: the
+/// address of the next instruction is pushed, and the flag is cleared so
+/// that the handler isn't interrupted itself. An interrupt raised while the
+/// flag is clear stays pending until it is set again. No interrupt is taken
+/// right after `sif` sets the flag, so that a handler can return with
+/// `#01 sif jmp` before it is interrupted again, nor right after a handler
+/// returns.
+pub struct Emulator {
+    pub yot_type: YotType,
+    pub memory: Memory,
+    pub devices: Devices,
+    pub program_counter: u64,
+    pub stack_pointer: u64,
+    /// The initial stack pointer, below which nothing can be popped.
+    pub stack_base: u64,
+    pub carry: bool,
+    pub interrupt_enabled: bool,
+    pub interrupt_address: u64,
+    pub interrupts: Interrupts,
+    /// Set by `sif` for a single instruction.
+    interrupt_inhibited: bool,
+    /// The return address and stack depth of every interrupt handler that
+    /// hasn't returned yet, innermost last.
+    handlers: Vec<(u64, u64)>,
+    /// The number of instructions executed so far.
+    pub steps: u64,
+}
+
+impl Emulator {
+    pub fn new(binary: &[u8], yot_type: YotType) -> Result<Emulator, Error> {
+        let address_size = yot_type as usize;
+        if binary.len() < address_size * 2 {
+            return Err(Error::HeaderMissing);
+        }
+        let mut memory = Memory::new(yot_type);
+        for (address, byte) in binary.iter().enumerate() {
+            memory.write(address as u64, *byte);
+        }
+        let stack_pointer = read_address(&binary[..address_size]);
+        let devices = Devices::new(memory.wrap(u64::MAX));
+        Ok(Emulator {
+            yot_type,
+            memory,
+            devices,
+            program_counter: read_address(&binary[address_size..address_size * 2]),
+            stack_pointer,
+            stack_base: stack_pointer,
+            carry: false,
+            interrupt_enabled: false,
+            interrupt_address: 0,
+            interrupts: Interrupts::new(),
+            interrupt_inhibited: false,
+            handlers: Vec::new(),
+            steps: 0,
+        })
+    }
+
+    /// The data stack from the bottom up.
+    pub fn stack(&self) -> Vec<u8> {
+        (0..self.depth()).map(|i| self.memory.read(self.stack_base.wrapping_add(i))).collect()
+    }
+
+    /// The number of bytes on the data stack.
+    pub fn depth(&self) -> u64 {
+        self.memory.wrap(self.stack_pointer.wrapping_sub(self.stack_base))
+    }
+
+    /// The address of the stack cell at the given depth.
+    fn cell(&self, depth: u8, address: u64) -> Result<u64, Error> {
+        if depth as u64 >= self.depth() {
+            return Err(Error::StackUnderflow { address });
+        }
+        Ok(self.memory.wrap(self.stack_pointer.wrapping_sub(depth as u64 + 1)))
+    }
+
+    pub fn push(&mut self, byte: u8) {
+        self.memory.write(self.stack_pointer, byte);
+        self.stack_pointer = self.memory.wrap(self.stack_pointer.wrapping_add(1));
+    }
+
+    fn pop(&mut self, address: u64) -> Result<u8, Error> {
+        let cell = self.cell(0, address)?;
+        self.stack_pointer = cell;
+        Ok(self.memory.read(cell))
+    }
+
+    pub fn push_address(&mut self, value: u64) {
+        for i in (0..self.yot_type as u64).rev() {
+            self.push((value >> (i * 8)) as u8);
+        }
+    }
+
+    fn pop_address(&mut self, address: u64) -> Result<u64, Error> {
+        let mut value: u64 = 0;
+        for i in 0..self.yot_type as u64 {
+            value |= (self.pop(address)? as u64) << (i * 8);
+        }
+        Ok(value)
+    }
+
+    fn pop_operands(&mut self, address: u64) -> Result<(u8, u8), Error> {
+        let b = self.pop(address)?;
+        let a = self.pop(address)?;
+        Ok((a, b))
+    }
+
+    /// Reads a byte for `fcm`, from the device mapped at the address if any.
+    fn load(&mut self, source: u64, address: u64) -> Result<u8, Error> {
+        let source = self.memory.wrap(source);
+        match self.devices.get(source) {
+            Some((device, offset)) => device.read(offset, self.steps)
+                .map_err(|io_error| Error::DeviceFailed { address, message: io_error.to_string() }),
+            None => Ok(self.memory.read(source)),
+        }
+    }
+
+    /// Writes a byte for `stm`, to the device mapped at the address if any.
+    fn store(&mut self, destination: u64, byte: u8, address: u64) -> Result<(), Error> {
+        let destination = self.memory.wrap(destination);
+        match self.devices.get(destination) {
+            Some((device, offset)) => device.write(offset, byte)
+                .map_err(|io_error| Error::DeviceFailed { address, message: io_error.to_string() }),
+            None => {
+                self.memory.write(destination, byte);
+                Ok(())
+            }
+        }
+    }
+
+    /// Executes the instruction at the program counter and takes an interrupt
+    /// if one is pending. On `brk` the program counter stays on the `brk`, so
+    /// stepping again halts again.
+    pub fn step(&mut self) -> Result<Step, Error> {
+        let address = self.program_counter;
+        self.interrupt_inhibited = false;
+        if self.execute()? == Step::Halted {
+            return Ok(Step::Halted);
+        }
+
+        self.interrupts.tick(self.steps);
+        if let Some((return_address, depth)) = self.handlers.last() {
+            if *return_address == self.program_counter && *depth == self.depth() {
+                let return_address = *return_address;
+                self.handlers.pop();
+                return Ok(Step::Returned { return_address });
+            }
+        }
+
+        if self.interrupts.devices && self.interrupt_enabled && !self.interrupts.pending {
+            self.interrupts.pending = self.devices.interrupt_pending()
+                .map_err(|io_error| Error::DeviceFailed { address, message: io_error.to_string() })?;
+        }
+        if self.interrupts.pending && self.interrupt_enabled && !self.interrupt_inhibited {
+            let return_address = self.program_counter;
+            self.interrupts.pending = false;
+            self.interrupt_enabled = false;
+            self.handlers.push((return_address, self.depth()));
+            self.push_address(return_address);
+            self.program_counter = self.memory.wrap(self.interrupt_address);
+            return Ok(Step::Interrupted { return_address });
+        }
+        Ok(Step::Continued)
+    }
+
+    fn execute(&mut self) -> Result<Step, Error> {
+        let address = self.program_counter;
+        let opcode = self.memory.read(address);
+        let next = self.memory.wrap(address.wrapping_add(1));
+
+        if opcode == PUSH_OPCODE {
+            let byte = self.memory.read(next);
+            self.push(byte);
+            self.program_counter = self.memory.wrap(next.wrapping_add(1));
+            self.steps += 1;
+            return Ok(Step::Continued);
+        }
+
+        if opcode == SUBROUTINE_JUMP_OPCODE {
+            let target = self.pop_address(address)?;
+            self.push_address(next);
+            self.program_counter = self.memory.wrap(target);
+            self.steps += 1;
+            return Ok(Step::Continued);
+        }
+
+        let instruction_kind = get_instruction_kind_by_opcode(opcode)
+            .ok_or(Error::OpcodeUnknown { address, opcode })?;
+        self.program_counter = next;
+        self.steps += 1;
+
+        match instruction_kind {
+            InstructionKind::Break => {
+                self.program_counter = address;
+                return Ok(Step::Halted);
+            }
+            InstructionKind::NoOperation => (),
+            InstructionKind::Drop => {
+                self.pop(address)?;
+            }
+            InstructionKind::Switch => {
+                let depth = self.pop(address)?;
+                let (top, cell) = (self.cell(0, address)?, self.cell(depth, address)?);
+                let (a, b) = (self.memory.read(top), self.memory.read(cell));
+                self.memory.write(top, b);
+                self.memory.write(cell, a);
+            }
+            InstructionKind::Pick => {
+                let depth = self.pop(address)?;
+                let cell = self.cell(depth, address)?;
+                let byte = self.memory.read(cell);
+                self.push(byte);
+            }
+            InstructionKind::Paste => {
+                let depth = self.pop(address)?;
+                let byte = self.pop(address)?;
+                let cell = self.cell(depth, address)?;
+                self.memory.write(cell, byte);
+            }
+            InstructionKind::Roll => {
+                let depth = self.pop(address)?;
+                let cell = self.cell(depth, address)?;
+                let byte = self.memory.read(cell);
+                for offset in 1..=depth as u64 {
+                    let from = self.memory.wrap(cell.wrapping_add(offset));
+                    let moved = self.memory.read(from);
+                    self.memory.write(self.memory.wrap(from.wrapping_sub(1)), moved);
+                }
+                let top = self.cell(0, address)?;
+                self.memory.write(top, byte);
+            }
+            InstructionKind::Insert => {
+                let depth = self.pop(address)?;
+                let cell = self.cell(depth, address)?;
+                let top = self.cell(0, address)?;
+                let byte = self.memory.read(top);
+                for i in 0..depth as u64 {
+                    let to = self.memory.wrap(top.wrapping_sub(i));
+                    let moved = self.memory.read(self.memory.wrap(to.wrapping_sub(1)));
+                    self.memory.write(to, moved);
+                }
+                self.memory.write(cell, byte);
+            }
+            InstructionKind::FetchMemory => {
+                let source = self.pop_address(address)?;
+                let byte = self.load(source, address)?;
+                self.push(byte);
+            }
+            InstructionKind::StoreMemory => {
+                let destination = self.pop_address(address)?;
+                let byte = self.pop(address)?;
+                self.store(destination, byte, address)?;
+            }
+            InstructionKind::Jump => {
+                let target = self.pop_address(address)?;
+                self.program_counter = self.memory.wrap(target);
+            }
+            InstructionKind::Branch => {
+                let target = self.pop_address(address)?;
+                if self.pop(address)? != 0 {
+                    self.program_counter = self.memory.wrap(target);
+                }
+            }
+            InstructionKind::SetInterruptFlag => {
+                let enabled = self.pop(address)? != 0;
+                self.interrupt_inhibited = enabled && !self.interrupt_enabled;
+                self.interrupt_enabled = enabled;
+            }
+            InstructionKind::SetInterruptAddress => {
+                self.interrupt_address = self.pop_address(address)?;
+            }
+            InstructionKind::AddWithCarry => {
+                let (a, b) = self.pop_operands(address)?;
+                let sum = a as u16 + b as u16 + self.carry as u16;
+                self.carry = sum > 0xff;
+                self.push(sum as u8);
+            }
+            InstructionKind::SubtractWithBorrow => {
+                let (a, b) = self.pop_operands(address)?;
+                let difference = a as i16 - b as i16 - self.carry as i16;
+                self.carry = difference < 0;
+                self.push(difference as u8);
+            }
+            InstructionKind::Add
+            | InstructionKind::Subtract
+            | InstructionKind::And
+            | InstructionKind::InclusiveOr
+            | InstructionKind::ExclusiveOr
+            | InstructionKind::ShiftLeft
+            | InstructionKind::ShiftRight
+            | InstructionKind::Equal
+            | InstructionKind::NotEqual => {
+                let (a, b) = self.pop_operands(address)?;
+                if let Some(result) = evaluate_pure(&instruction_kind, a, b) {
+                    self.push(result);
+                }
+            }
+        }
+
+        Ok(Step::Continued)
+    }
+
+    /// Runs the program until it reaches `brk`, or fails after executing the
+    /// given number of instructions.
+    pub fn run(&mut self, max_steps: Option<u64>) -> Result<(), Error> {
+        self.run_with(max_steps, Emulator::step)
+    }
+
+    /// Runs the program like `run`, but takes every step through `step`,
+    /// which calls `Emulator::step` and may look at the emulator before and
+    /// after it.
+    pub fn run_with(
+        &mut self,
+        max_steps: Option<u64>,
+        mut step: impl FnMut(&mut Emulator) -> Result<Step, Error>,
+    ) -> Result<(), Error> {
+        loop {
+            if matches!(max_steps, Some(max_steps) if self.steps >= max_steps) {
+                return Err(Error::StepLimitReached { steps: self.steps });
+            }
+            if step(self)? == Step::Halted {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::emitter::{Optimizations, emit};
+    use super::super::tokenizer::tokenize;
+
+    const YOT_TYPES: [YotType; 4] = [YotType::Y8, YotType::Y16, YotType::Y32, YotType::Y64];
+    const STACK_POINTER: u64 = 0x80;
+
+    fn assemble(source: &str, yot_type: YotType, initial_stack_pointer: u64) -> Vec<u8> {
+        let tokens = tokenize(source, 0).unwrap();
+        emit(&tokens, yot_type, initial_stack_pointer, None, &Optimizations::none()).unwrap().binary
+    }
+
+    fn run(source: &str, yot_type: YotType) -> Emulator {
+        let mut emulator = Emulator::new(&assemble(source, yot_type, STACK_POINTER), yot_type).unwrap();
+        emulator.run(Some(1000)).unwrap();
+        emulator
+    }
+
+    /// Data literals pushing an address, most significant byte first.
+    fn address_literal(address: u64, yot_type: YotType) -> String {
+        address.to_be_bytes()[8 - yot_type as usize..].iter()
+            .map(|byte| format!("#{:02x}", byte))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn reads_the_header_at_the_width_of_the_yot_type() {
+        for yot_type in YOT_TYPES {
+            let stack_pointer = 0x8877665544332211 & Memory::new(yot_type).wrap(u64::MAX);
+            let emulator = Emulator::new(&assemble("brk", yot_type, stack_pointer), yot_type).unwrap();
+            assert_eq!(emulator.stack_pointer, stack_pointer);
+            assert_eq!(emulator.stack_base, stack_pointer);
+            assert_eq!(emulator.program_counter, yot_type as u64 * 2);
+        }
+        assert_eq!(Emulator::new(&[0x00; 15], YotType::Y64).err(), Some(Error::HeaderMissing));
+    }
+
+    #[test]
+    fn pushes_return_addresses_most_significant_byte_first() {
+        for yot_type in YOT_TYPES {
+            let emulator = run("callee\n:callee\nbrk", yot_type);
+            let address_size = yot_type as u64;
+            // the header, the pushes of the callee address and the opcode
+            let return_address = address_size * 2 + address_size * 2 + 1;
+            assert_eq!(emulator.stack(), return_address.to_be_bytes()[8 - address_size as usize..]);
+            assert_eq!(emulator.program_counter, return_address);
+        }
+    }
+
+    #[test]
+    fn returns_by_popping_the_whole_address() {
+        for yot_type in YOT_TYPES {
+            let emulator = run("#2a callee #2b brk\n:callee\njmp", yot_type);
+            assert_eq!(emulator.stack(), [0x2a, 0x2b]);
+        }
+    }
+
+    #[test]
+    fn stores_and_fetches_at_addresses_of_the_yot_type_width() {
+        for yot_type in YOT_TYPES {
+            let address = 0x80 << ((yot_type as u64 - 1) * 8) | 0x42;
+            let literal = address_literal(address, yot_type);
+            let emulator = run(&format!("#2a {} stm #00 {} fcm brk", literal, literal), yot_type);
+            assert_eq!(emulator.stack(), [0x00, 0x2a]);
+            assert_eq!(emulator.memory.read(address), 0x2a);
+        }
+    }
+
+    #[test]
+    fn branches_to_a_popped_address_on_a_nonzero_byte() {
+        for yot_type in YOT_TYPES {
+            let emulator = run("#01 @taken bnc #2a\n:taken\n#00 @skipped bnc #2b\n:skipped\nbrk", yot_type);
+            assert_eq!(emulator.stack(), [0x2b]);
+        }
+    }
+
+    #[test]
+    fn wraps_the_stack_around_the_address_space() {
+        for yot_type in YOT_TYPES {
+            let last_address = Memory::new(yot_type).wrap(u64::MAX);
+            let binary = assemble("#01 #02 drp #03 brk", yot_type, last_address);
+            let mut emulator = Emulator::new(&binary, yot_type).unwrap();
+            emulator.run(Some(1000)).unwrap();
+            assert_eq!(emulator.stack(), [0x01, 0x03]);
+            assert_eq!(emulator.stack_pointer, 1);
+            assert_eq!(emulator.memory.read(last_address), 0x01);
+            assert_eq!(emulator.memory.read(0), 0x03);
+        }
+    }
+
+    #[test]
+    fn wraps_memory_accesses_around_the_address_space() {
+        let mut memory = Memory::new(YotType::Y16);
+        memory.write(0x12345, 0x2a);
+        assert_eq!(memory.read(0x2345), 0x2a);
+        let mut memory = Memory::new(YotType::Y64);
+        memory.write(u64::MAX, 0x2a);
+        assert_eq!(memory.read(u64::MAX), 0x2a);
+        assert_eq!(memory.read(u64::MAX >> 8), 0x00);
+    }
+
+    #[test]
+    fn moves_stack_cells_by_depth() {
+        for yot_type in YOT_TYPES {
+            assert_eq!(run("#01 #02 #03 #02 swc brk", yot_type).stack(), [0x03, 0x02, 0x01]);
+            assert_eq!(run("#01 #02 #03 #02 pck brk", yot_type).stack(), [0x01, 0x02, 0x03, 0x01]);
+            assert_eq!(run("#01 #02 #03 #2a #02 pst brk", yot_type).stack(), [0x2a, 0x02, 0x03]);
+            assert_eq!(run("#01 #02 #03 #02 rol brk", yot_type).stack(), [0x02, 0x03, 0x01]);
+            assert_eq!(run("#01 #02 #03 #02 ins brk", yot_type).stack(), [0x03, 0x01, 0x02]);
+            assert_eq!(run("#01 #02 drp brk", yot_type).stack(), [0x01]);
+        }
+    }
+
+    #[test]
+    fn carries_between_additions_and_subtractions() {
+        assert_eq!(run("#ff #01 adc #00 #00 adc brk", YotType::Y8).stack(), [0x00, 0x01]);
+        assert_eq!(run("#00 #01 sbb #05 #02 sbb #05 #02 sbb brk", YotType::Y8).stack(), [0xff, 0x02, 0x03]);
+        assert_eq!(run("#ff #01 add #00 #00 adc brk", YotType::Y8).stack(), [0x00, 0x00]);
+    }
+
+    #[test]
+    fn fails_on_stack_underflow() {
+        for yot_type in YOT_TYPES {
+            let mut emulator = Emulator::new(&assemble("drp", yot_type, STACK_POINTER), yot_type).unwrap();
+            let address = yot_type as u64 * 2;
+            assert_eq!(emulator.run(Some(10)), Err(Error::StackUnderflow { address }));
+        }
+        // a single byte is a whole address on yot-8 only
+        for yot_type in [YotType::Y16, YotType::Y32, YotType::Y64] {
+            let mut emulator = Emulator::new(&assemble("#01 jmp", yot_type, STACK_POINTER), yot_type).unwrap();
+            let address = yot_type as u64 * 2 + 2;
+            assert_eq!(emulator.run(Some(10)), Err(Error::StackUnderflow { address }));
+        }
+    }
+
+    #[test]
+    fn maps_devices_only_within_the_address_space() {
+        let mut emulator = Emulator::new(&[0x00, 0x02, 0x00], YotType::Y8).unwrap();
+        assert_eq!(emulator.devices.map(0xff00, Box::new(CycleCounter::new())), Err(Error::DeviceOutsideAddressSpace {
+            address: 0xff00,
+            size: 8,
+            last_address: 0xff,
+        }));
+        assert!(emulator.devices.map(0xfa, Box::new(CycleCounter::new())).is_err());
+        assert_eq!(emulator.devices.map(0xf8, Box::new(CycleCounter::new())), Ok(()));
+
+        for yot_type in [YotType::Y16, YotType::Y32, YotType::Y64] {
+            let binary = assemble("brk", yot_type, STACK_POINTER);
+            let mut emulator = Emulator::new(&binary, yot_type).unwrap();
+            assert_eq!(emulator.devices.map(0xff00, Box::new(CycleCounter::new())), Ok(()));
+            let last_address = emulator.memory.wrap(u64::MAX);
+            assert!(emulator.devices.map(last_address, Box::new(CycleCounter::new())).is_err());
+        }
+    }
+
+    #[test]
+    fn stays_on_break() {
+        for yot_type in YOT_TYPES {
+            let mut emulator = Emulator::new(&assemble("nop brk", yot_type, STACK_POINTER), yot_type).unwrap();
+            assert_eq!(emulator.step(), Ok(Step::Continued));
+            assert_eq!(emulator.step(), Ok(Step::Halted));
+            assert_eq!(emulator.step(), Ok(Step::Halted));
+            assert_eq!(emulator.program_counter, yot_type as u64 * 2 + 1);
+        }
+    }
+
+    /// Runs the program with the interrupts raised by `raise` and returns
+    /// the emulator along with every step that wasn't `Step::Continued`, by
+    /// the number of instructions executed then.
+    fn run_interrupted(source: &str, raise: impl FnOnce(&mut Interrupts)) -> (Emulator, Vec<(u64, Step)>) {
+        let mut emulator = Emulator::new(&assemble(source, YotType::Y8, STACK_POINTER), YotType::Y8).unwrap();
+        raise(&mut emulator.interrupts);
+        let mut steps: Vec<(u64, Step)> = Vec::new();
+        loop {
+            match emulator.step().unwrap() {
+                Step::Continued => (),
+                Step::Halted => return (emulator, steps),
+                step => steps.push((emulator.steps, step)),
+            }
+            assert!(emulator.steps < 100);
+        }
+    }
+
+    /// The address of the instruction at the label.
+    fn label_address(source: &str, label: &str) -> u64 {
+        let tokens = tokenize(source, 0).unwrap();
+        let assembly = emit(&tokens, YotType::Y8, STACK_POINTER, None, &Optimizations::none()).unwrap();
+        assembly.symbols.iter().find(|symbol| symbol.label == label).unwrap().address as u64
+    }
+
+    /// Counts the interrupts it handles at 0x70.
+    const HANDLER: &str = "
+        @handler sia #01 sif
+        nop
+        :interrupted
+        nop nop nop brk
+        :handler
+        #70 fcm #01 add #70 stm #01 sif jmp
+    ";
+
+    #[test]
+    fn takes_no_interrupt_right_after_setting_the_flag() {
+        // raised after the first instruction, while the flag is clear
+        let (emulator, steps) = run_interrupted(HANDLER, |interrupts| interrupts.schedule(1));
+        let return_address = label_address(HANDLER, "interrupted");
+        assert_eq!(steps, [(5, Step::Interrupted { return_address }), (14, Step::Returned { return_address })]);
+        assert_eq!(emulator.memory.read(0x70), 1);
+        assert!(emulator.stack().is_empty());
+        assert!(emulator.interrupt_enabled);
+    }
+
+    #[test]
+    fn takes_interrupts_raised_after_an_instruction_count() {
+        let interrupted = label_address(HANDLER, "interrupted");
+        let (_, steps) = run_interrupted(HANDLER, |interrupts| interrupts.schedule(6));
+        let return_address = interrupted + 1;
+        assert_eq!(steps, [(6, Step::Interrupted { return_address }), (15, Step::Returned { return_address })]);
+
+        let (emulator, steps) = run_interrupted(HANDLER, |interrupts| interrupts.every(7));
+        // the second one is raised within the handler and taken one
+        // instruction after it returns
+        assert_eq!(steps, [
+            (7, Step::Interrupted { return_address: interrupted + 2 }),
+            (16, Step::Returned { return_address: interrupted + 2 }),
+            (17, Step::Interrupted { return_address: interrupted + 3 }),
+            (26, Step::Returned { return_address: interrupted + 3 }),
+        ]);
+        assert_eq!(emulator.memory.read(0x70), 2);
+    }
+
+    #[test]
+    fn returns_from_nested_interrupt_handlers_innermost_first() {
+        // the handler can be interrupted until it clears the flag to return
+        let source = "
+            @handler sia #01 sif
+            nop
+            :interrupted
+            nop brk
+            :handler
+            #01 sif
+            #70
+            :nested
+            fcm #01 add #70 stm
+            #00 sif #01 sif jmp
+        ";
+        let (emulator, steps) = run_interrupted(source, |interrupts| {
+            interrupts.schedule(5);
+            interrupts.schedule(8);
+        });
+        let interrupted = label_address(source, "interrupted");
+        let nested = label_address(source, "nested");
+        assert_eq!(steps, [
+            (5, Step::Interrupted { return_address: interrupted }),
+            (8, Step::Interrupted { return_address: nested }),
+            (21, Step::Returned { return_address: nested }),
+            (31, Step::Returned { return_address: interrupted }),
+        ]);
+        assert_eq!(emulator.memory.read(0x70), 2);
+        assert!(emulator.stack().is_empty());
+    }
+}
@@ -0,0 +1,189 @@
+use std::io::{self, BufReader, Read, Write};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// The byte GDB sends outside of any packet to interrupt the target.
+const INTERRUPT: u8 = 0x03;
+
+/// What GDB sent.
+pub enum Received {
+    Packet(String),
+    Interrupt,
+    /// GDB hung up.
+    Closed,
+}
+
+/// A connection to GDB speaking the framing of the remote serial protocol:
+/// packets are sent as `$<data>#<checksum>` and acknowledged with `+`
+/// until GDB turns acknowledgements off.
+pub struct Connection<W: Write> {
+    /// The bytes from GDB, read on a thread of their own so that the target
+    /// can check for an interrupt while it runs.
+    bytes: Receiver<io::Result<u8>>,
+    output: W,
+    pub acknowledge: bool,
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |checksum: u8, byte| checksum.wrapping_add(*byte))
+}
+
+impl<W: Write> Connection<W> {
+    pub fn new(input: impl Read + Send + 'static, output: W) -> Connection<W> {
+        let (sender, bytes) = mpsc::channel();
+        thread::spawn(move || {
+            for byte in BufReader::new(input).bytes() {
+                let failed = byte.is_err();
+                if sender.send(byte).is_err() || failed {
+                    break;
+                }
+            }
+        });
+        Connection { bytes, output, acknowledge: true }
+    }
+
+    fn next(&mut self) -> io::Result<Option<u8>> {
+        match self.bytes.recv() {
+            Ok(byte) => byte.map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Waits for the next packet or interrupt. Packets with a wrong checksum
+    /// are asked to be sent again.
+    pub fn receive(&mut self) -> io::Result<Received> {
+        loop {
+            match self.next()? {
+                None => return Ok(Received::Closed),
+                Some(INTERRUPT) => return Ok(Received::Interrupt),
+                Some(b'$') => (),
+                // acknowledgements and anything between packets
+                Some(_) => continue,
+            }
+            let mut data: Vec<u8> = Vec::new();
+            loop {
+                match self.next()? {
+                    None => return Ok(Received::Closed),
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                }
+            }
+            let mut digits = [0; 2];
+            for digit in digits.iter_mut() {
+                *digit = match self.next()? {
+                    Some(digit) => digit,
+                    None => return Ok(Received::Closed),
+                };
+            }
+            let expected = std::str::from_utf8(&digits).ok().and_then(|digits| u8::from_str_radix(digits, 16).ok());
+            if !self.acknowledge {
+                return Ok(Received::Packet(String::from_utf8_lossy(&data).into_owned()));
+            }
+            if expected == Some(checksum(&data)) {
+                self.output.write_all(b"+")?;
+                self.output.flush()?;
+                return Ok(Received::Packet(String::from_utf8_lossy(&data).into_owned()));
+            }
+            self.output.write_all(b"-")?;
+            self.output.flush()?;
+        }
+    }
+
+    /// Whether GDB asked to interrupt the target, without waiting.
+    pub fn interrupt_requested(&mut self) -> bool {
+        loop {
+            match self.bytes.try_recv() {
+                Ok(Ok(INTERRUPT)) => return true,
+                // GDB doesn't send packets while the target runs
+                Ok(Ok(_)) => continue,
+                Ok(Err(_)) | Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => return false,
+            }
+        }
+    }
+
+    /// Sends a packet, escaping the bytes that would end it early.
+    pub fn send(&mut self, data: &str) -> io::Result<()> {
+        let mut escaped: Vec<u8> = Vec::with_capacity(data.len());
+        for byte in data.bytes() {
+            match byte {
+                b'$' | b'#' | b'}' | b'*' => escaped.extend([b'}', byte ^ 0x20]),
+                byte => escaped.push(byte),
+            }
+        }
+        write!(self.output, "$")?;
+        self.output.write_all(&escaped)?;
+        write!(self.output, "#{:02x}", checksum(&escaped))?;
+        self.output.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn connection(input: &[u8]) -> Connection<Vec<u8>> {
+        Connection::new(Cursor::new(input.to_vec()), Vec::new())
+    }
+
+    fn packet(received: Received) -> Option<String> {
+        match received {
+            Received::Packet(packet) => Some(packet),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn acknowledges_packets_with_a_correct_checksum() {
+        let mut connection = connection(b"+$g#67$OK#9a");
+        assert_eq!(packet(connection.receive().unwrap()), Some("g".to_owned()));
+        assert_eq!(packet(connection.receive().unwrap()), Some("OK".to_owned()));
+        assert!(matches!(connection.receive().unwrap(), Received::Closed));
+        assert_eq!(connection.output, b"++");
+    }
+
+    #[test]
+    fn asks_for_packets_with_a_wrong_checksum_again() {
+        let mut connection = connection(b"$g#00$g#67");
+        assert_eq!(packet(connection.receive().unwrap()), Some("g".to_owned()));
+        assert_eq!(connection.output, b"-+");
+    }
+
+    #[test]
+    fn stops_acknowledging_when_asked_to() {
+        let mut connection = connection(b"$g#00");
+        connection.acknowledge = false;
+        assert_eq!(packet(connection.receive().unwrap()), Some("g".to_owned()));
+        assert!(connection.output.is_empty());
+    }
+
+    #[test]
+    fn receives_interrupts_and_hang_ups() {
+        let mut connection = connection(b"\x03$g#6");
+        assert!(matches!(connection.receive().unwrap(), Received::Interrupt));
+        // the packet ends within its checksum
+        assert!(matches!(connection.receive().unwrap(), Received::Closed));
+    }
+
+    #[test]
+    fn checks_for_interrupts_without_waiting() {
+        let mut connection = connection(b"+\x03");
+        // the bytes arrive on a thread of their own
+        while !connection.interrupt_requested() {
+            thread::yield_now();
+        }
+        assert!(!connection.interrupt_requested());
+    }
+
+    #[test]
+    fn escapes_and_checksums_sent_packets() {
+        let mut connection = connection(b"");
+        connection.send("OK").unwrap();
+        connection.send("a$#}*").unwrap();
+        let escaped = b"a}\x04}\x03}]}\x0a";
+        let mut expected = b"$OK#9a$".to_vec();
+        expected.extend(escaped);
+        expected.extend(format!("#{:02x}", checksum(escaped)).bytes());
+        assert_eq!(connection.output, expected);
+    }
+}
@@ -0,0 +1,33 @@
+use std::fmt;
+use std::io;
+use crate::reporter::{Diagnostic, Report, Reporter};
+
+#[derive(Debug)]
+pub enum Error {
+    CouldNotListen { address: String, io_error: io::Error },
+    ConnectionFailed { io_error: io::Error },
+}
+
+impl Report for Error {
+    fn report(&self, r: &Reporter) {
+        r.write(Diagnostic {
+            message: self.to_string(),
+            labels: vec![],
+        });
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Error::CouldNotListen { address, io_error } => write!(f,
+                "couldn't listen on {}: {}", address, io_error,
+            ),
+            Error::ConnectionFailed { io_error } => write!(f,
+                "the connection to GDB failed: {}", io_error,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,447 @@
+pub use error::Error;
+use connection::{Connection, Received};
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use super::emulator::{self, Emulator, Step};
+
+mod connection;
+mod error;
+
+/// How many instructions to execute between checks for an interrupt from GDB.
+const STEPS_PER_POLL: u64 = 0x1000;
+
+/// The largest packet GDB may send and the stub replies with, in bytes.
+const PACKET_SIZE: usize = 0x4000;
+
+const SIGINT: u8 = 0x02;
+const SIGILL: u8 = 0x04;
+const SIGTRAP: u8 = 0x05;
+const SIGABRT: u8 = 0x06;
+const SIGSEGV: u8 = 0x0b;
+
+/// The registers, numbered in the order of the target description.
+const REGISTERS: [&str; 2] = ["pc", "sp"];
+
+/// Describes the registers, both as wide as an address of the Yot type. GDB
+/// has no Yot architecture, so the description names none.
+fn target_description(address_bits: usize) -> String {
+    let mut xml: String = String::new();
+    let _ = writeln!(xml, "<?xml version=\"1.0\"?>");
+    let _ = writeln!(xml, "<!DOCTYPE target SYSTEM \"gdb-target.dtd\">");
+    let _ = writeln!(xml, "<target version=\"1.0\">");
+    let _ = writeln!(xml, "  <feature name=\"org.ysm.yot\">");
+    let _ = writeln!(xml, "    <reg name=\"pc\" bitsize=\"{}\" type=\"code_ptr\" regnum=\"0\"/>", address_bits);
+    let _ = writeln!(xml, "    <reg name=\"sp\" bitsize=\"{}\" type=\"data_ptr\" regnum=\"1\"/>", address_bits);
+    let _ = writeln!(xml, "  </feature>");
+    let _ = writeln!(xml, "</target>");
+    xml
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len()).step_by(2).map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok())).collect()
+}
+
+fn parse_number(hex: &str) -> Option<u64> {
+    u64::from_str_radix(hex, 16).ok()
+}
+
+/// Parses `<address>,<length>` as sent with memory and breakpoint packets.
+fn parse_range(range: &str) -> Option<(u64, u64)> {
+    let (address, length) = range.split_once(',')?;
+    Some((parse_number(address)?, parse_number(length)?))
+}
+
+/// The signal an emulation error is reported to GDB as.
+fn signal(error: &emulator::Error) -> u8 {
+    match error {
+        emulator::Error::OpcodeUnknown { .. } => SIGILL,
+        emulator::Error::StackUnderflow { .. } => SIGSEGV,
+        _ => SIGABRT,
+    }
+}
+
+/// Lets GDB control the emulator over the remote serial protocol. Registers
+/// are sent from the most significant byte down, like addresses in memory,
+/// so GDB should be told `set endian big`. Memory is read and written
+/// directly, bypassing any mapped devices. `brk` stops the target like a
+/// breakpoint, and the data stack can be shown with `monitor stack`.
+///
+/// Only clients that take the register layout from the target description
+/// work with the stub, such as debugger front ends or scripts speaking the
+/// protocol. A stock GDB does not: it has no Yot architecture, so it applies
+/// the register layout of its own default architecture and rejects the
+/// register replies.
+pub struct Stub<W: Write> {
+    emulator: Emulator,
+    connection: Connection<W>,
+    breakpoints: BTreeSet<u64>,
+}
+
+impl<W: Write> Stub<W> {
+    pub fn new(emulator: Emulator, input: impl Read + Send + 'static, output: W) -> Stub<W> {
+        Stub {
+            emulator,
+            connection: Connection::new(input, output),
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    fn address_size(&self) -> usize {
+        self.emulator.yot_type as usize
+    }
+
+    fn encode_register(&self, value: u64) -> String {
+        encode_hex(&value.to_be_bytes()[8 - self.address_size()..])
+    }
+
+    fn decode_register(&self, hex: &str) -> Option<u64> {
+        let bytes = decode_hex(hex)?;
+        (bytes.len() == self.address_size()).then(|| bytes.iter().fold(0, |value, byte| (value << 8) | *byte as u64))
+    }
+
+    fn register(&self, number: u64) -> Option<u64> {
+        match number {
+            0 => Some(self.emulator.program_counter),
+            1 => Some(self.emulator.stack_pointer),
+            _ => None,
+        }
+    }
+
+    fn set_register(&mut self, number: u64, value: u64) -> bool {
+        let value = self.emulator.memory.wrap(value);
+        match number {
+            0 => self.emulator.program_counter = value,
+            1 => self.emulator.stack_pointer = value,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Executes instructions until a breakpoint, `brk`, an error or an
+    /// interrupt from GDB, or only one of them if `single`, and returns the
+    /// stop reply.
+    fn resume(&mut self, single: bool) -> String {
+        loop {
+            match self.emulator.step() {
+                Ok(Step::Halted) => return format!("S{:02x}", SIGTRAP),
+                Ok(_) => (),
+                Err(error) => return format!("S{:02x}", signal(&error)),
+            }
+            if single || self.breakpoints.contains(&self.emulator.program_counter) {
+                return format!("S{:02x}", SIGTRAP);
+            }
+            if self.emulator.steps.is_multiple_of(STEPS_PER_POLL) && self.connection.interrupt_requested() {
+                return format!("S{:02x}", SIGINT);
+            }
+        }
+    }
+
+    fn read_memory(&self, address: u64, length: u64) -> String {
+        let bytes: Vec<u8> = (0..length)
+            .map(|i| self.emulator.memory.read(address.wrapping_add(i)))
+            .collect();
+        encode_hex(&bytes)
+    }
+
+    /// Replies to a `qRcmd`, a `monitor` command.
+    fn monitor(&mut self, command: &str) -> String {
+        let output = match command.trim() {
+            "stack" => {
+                let stack: Vec<String> = self.emulator.stack().iter().map(|byte| format!("{:02x}", byte)).collect();
+                format!("{}\n", stack.join(" "))
+            }
+            _ => "unknown monitor command, try `monitor stack`\n".to_owned(),
+        };
+        // the output is sent as console output before the final reply
+        if self.connection.send(&format!("O{}", encode_hex(output.as_bytes()))).is_err() {
+            return "E01".to_owned();
+        }
+        "OK".to_owned()
+    }
+
+    /// Replies to a single packet, or returns `None` if the session is over.
+    fn reply(&mut self, packet: &str) -> Option<String> {
+        let (command, arguments) = packet.split_at(packet.chars().next().map_or(0, |c| c.len_utf8()));
+        let reply = match command {
+            "?" => format!("S{:02x}", SIGTRAP),
+            "g" => REGISTERS.iter().enumerate()
+                .filter_map(|(number, _)| self.register(number as u64))
+                .map(|value| self.encode_register(value))
+                .collect(),
+            "G" => {
+                let hex_size = self.address_size() * 2;
+                let values: Option<Vec<u64>> = (0..REGISTERS.len())
+                    .map(|number| arguments.get(number * hex_size..(number + 1) * hex_size).and_then(|hex| self.decode_register(hex)))
+                    .collect();
+                match values {
+                    Some(values) => {
+                        for (number, value) in values.into_iter().enumerate() {
+                            self.set_register(number as u64, value);
+                        }
+                        "OK".to_owned()
+                    }
+                    None => "E01".to_owned(),
+                }
+            }
+            "p" => match parse_number(arguments).and_then(|number| self.register(number)) {
+                Some(value) => self.encode_register(value),
+                None => "E01".to_owned(),
+            },
+            "P" => match arguments.split_once('=').and_then(|(number, hex)| Some((parse_number(number)?, self.decode_register(hex)?))) {
+                Some((number, value)) if self.set_register(number, value) => "OK".to_owned(),
+                _ => "E01".to_owned(),
+            },
+            "m" => match parse_range(arguments) {
+                // every byte takes two hex digits in the reply
+                Some((address, length)) if length <= (PACKET_SIZE / 2) as u64 => self.read_memory(address, length),
+                _ => "E01".to_owned(),
+            },
+            "M" => match arguments.split_once(':').and_then(|(range, hex)| Some((parse_range(range)?, decode_hex(hex)?))) {
+                Some(((address, length), bytes)) if bytes.len() as u64 == length => {
+                    for (i, byte) in bytes.iter().enumerate() {
+                        self.emulator.memory.write(address.wrapping_add(i as u64), *byte);
+                    }
+                    "OK".to_owned()
+                }
+                _ => "E01".to_owned(),
+            },
+            "Z" | "z" => match arguments.split_once(',').and_then(|(kind, range)| Some((kind, parse_range(range)?))) {
+                // software and hardware breakpoints are the same to an emulator
+                Some(("0", (address, _))) | Some(("1", (address, _))) => {
+                    let address = self.emulator.memory.wrap(address);
+                    if command == "Z" {
+                        self.breakpoints.insert(address);
+                    } else {
+                        self.breakpoints.remove(&address);
+                    }
+                    "OK".to_owned()
+                }
+                Some(_) => String::new(),
+                None => "E01".to_owned(),
+            },
+            "s" | "c" => {
+                if let Some(address) = parse_number(arguments) {
+                    self.emulator.program_counter = self.emulator.memory.wrap(address);
+                }
+                self.resume(command == "s")
+            }
+            "H" => "OK".to_owned(),
+            "T" => "OK".to_owned(),
+            "D" => {
+                let _ = self.connection.send("OK");
+                return None;
+            }
+            "k" => return None,
+            _ => self.query(packet),
+        };
+        Some(reply)
+    }
+
+    /// Replies to the general queries and settings that matter to GDB, and
+    /// with an empty reply, meaning unsupported, to any others.
+    fn query(&mut self, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return format!("PacketSize={:x};qXfer:features:read+;QStartNoAckMode+", PACKET_SIZE);
+        }
+        if packet == "QStartNoAckMode" {
+            // the reply is still acknowledged, so acknowledgements stop after it
+            if self.connection.send("OK").is_ok() {
+                self.connection.acknowledge = false;
+            }
+            return String::new();
+        }
+        if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let description = target_description(self.address_size() * 8);
+            return match parse_range(range) {
+                Some((offset, length)) => {
+                    let start = (offset as usize).min(description.len());
+                    let end = start.saturating_add(length as usize).min(description.len());
+                    let more = if end < description.len() { "m" } else { "l" };
+                    format!("{}{}", more, &description[start..end])
+                }
+                None => "E01".to_owned(),
+            };
+        }
+        if let Some(hex) = packet.strip_prefix("qRcmd,") {
+            return match decode_hex(hex) {
+                Some(command) => self.monitor(&String::from_utf8_lossy(&command)),
+                None => "E01".to_owned(),
+            };
+        }
+        match packet {
+            "qAttached" => "1".to_owned(),
+            "qC" => "QC1".to_owned(),
+            "qfThreadInfo" => "m1".to_owned(),
+            "qsThreadInfo" => "l".to_owned(),
+            _ => String::new(),
+        }
+    }
+
+    /// Serves GDB until it detaches, kills the target or hangs up.
+    pub fn serve(&mut self) -> Result<(), Error> {
+        loop {
+            let packet = match self.connection.receive().map_err(|io_error| Error::ConnectionFailed { io_error })? {
+                Received::Packet(packet) => packet,
+                Received::Interrupt => {
+                    self.connection.send(&format!("S{:02x}", SIGINT)).map_err(|io_error| Error::ConnectionFailed { io_error })?;
+                    continue;
+                }
+                Received::Closed => return Ok(()),
+            };
+            let reply = match self.reply(&packet) {
+                Some(reply) => reply,
+                None => return Ok(()),
+            };
+            // QStartNoAckMode already replied
+            if packet == "QStartNoAckMode" {
+                continue;
+            }
+            self.connection.send(&reply).map_err(|io_error| Error::ConnectionFailed { io_error })?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{InstructionKind, YotType, get_opcode};
+    use std::io;
+
+    const YOT_TYPES: [YotType; 4] = [YotType::Y8, YotType::Y16, YotType::Y32, YotType::Y64];
+
+    /// A stub for the program with the stack at 0x40 and the entry point
+    /// right after the header.
+    fn stub(yot_type: YotType, program: &[u8]) -> Stub<Vec<u8>> {
+        let address_size = yot_type as usize;
+        let mut binary: Vec<u8> = Vec::new();
+        binary.extend(&0x40u64.to_be_bytes()[8 - address_size..]);
+        binary.extend(&(address_size as u64 * 2).to_be_bytes()[8 - address_size..]);
+        binary.extend(program);
+        Stub::new(Emulator::new(&binary, yot_type).unwrap(), io::empty(), Vec::new())
+    }
+
+    /// A register value as wide as an address, in hex digits.
+    fn hex(yot_type: YotType, value: u64) -> String {
+        format!("{:01$x}", value, yot_type as usize * 2)
+    }
+
+    fn reply(stub: &mut Stub<Vec<u8>>, packet: &str) -> String {
+        stub.reply(packet).unwrap()
+    }
+
+    #[test]
+    fn reads_and_writes_all_registers_at_every_width() {
+        for yot_type in YOT_TYPES {
+            let mut stub = stub(yot_type, &[0x00]);
+            let entry_point = yot_type as u64 * 2;
+            assert_eq!(reply(&mut stub, "g"), hex(yot_type, entry_point) + &hex(yot_type, 0x40), "{:?}", yot_type);
+
+            assert_eq!(reply(&mut stub, &format!("G{}{}", hex(yot_type, 0x12), hex(yot_type, 0x34))), "OK");
+            assert_eq!((stub.emulator.program_counter, stub.emulator.stack_pointer), (0x12, 0x34));
+            // both registers, as wide as an address
+            assert_eq!(reply(&mut stub, &format!("G{}", hex(yot_type, 0x12))), "E01");
+            assert_eq!(reply(&mut stub, &format!("G{}0", hex(yot_type, 0x12))), "E01");
+            assert_eq!(reply(&mut stub, "g"), hex(yot_type, 0x12) + &hex(yot_type, 0x34));
+        }
+    }
+
+    #[test]
+    fn reads_and_writes_single_registers_at_every_width() {
+        for yot_type in YOT_TYPES {
+            let mut stub = stub(yot_type, &[0x00]);
+            assert_eq!(reply(&mut stub, "p0"), hex(yot_type, yot_type as u64 * 2), "{:?}", yot_type);
+            assert_eq!(reply(&mut stub, "p1"), hex(yot_type, 0x40));
+            assert_eq!(reply(&mut stub, "p2"), "E01");
+
+            assert_eq!(reply(&mut stub, &format!("P1={}", hex(yot_type, 0x50))), "OK");
+            assert_eq!(stub.emulator.stack_pointer, 0x50);
+            assert_eq!(reply(&mut stub, &format!("P2={}", hex(yot_type, 0x50))), "E01");
+            assert_eq!(reply(&mut stub, "P1=5"), "E01");
+            assert_eq!(reply(&mut stub, &format!("P1={}00", hex(yot_type, 0x50))), "E01");
+        }
+    }
+
+    #[test]
+    fn writes_memory() {
+        let mut stub = stub(YotType::Y8, &[0x00]);
+        assert_eq!(reply(&mut stub, "M40,2:abcd"), "OK");
+        assert_eq!(reply(&mut stub, "m3f,4"), "00abcd00");
+        assert_eq!(reply(&mut stub, "M40,3:abcd"), "E01");
+        assert_eq!(reply(&mut stub, "M40,1:a"), "E01");
+        // memory wraps around at the end of the address space
+        assert_eq!(reply(&mut stub, "Mff,2:1234"), "OK");
+        assert_eq!(reply(&mut stub, "m0,1"), "34");
+    }
+
+    #[test]
+    fn continues_to_breakpoints_until_removed() {
+        let nop = get_opcode(&InstructionKind::NoOperation);
+        let mut stub = stub(YotType::Y16, &[nop, nop, nop, 0x00]);
+        assert_eq!(reply(&mut stub, "Z0,6,1"), "OK");
+        assert_eq!(reply(&mut stub, "c"), "S05");
+        assert_eq!(stub.emulator.program_counter, 6);
+        assert_eq!(reply(&mut stub, "z0,6,1"), "OK");
+        assert_eq!(reply(&mut stub, "c4"), "S05");
+        assert_eq!(stub.emulator.steps, 6);
+        // watchpoints are unsupported
+        assert_eq!(reply(&mut stub, "Z2,6,1"), "");
+        assert_eq!(reply(&mut stub, "Z0,6"), "E01");
+    }
+
+    #[test]
+    fn steps_single_instructions() {
+        let nop = get_opcode(&InstructionKind::NoOperation);
+        let mut stub = stub(YotType::Y8, &[nop, nop, 0x00]);
+        assert_eq!(reply(&mut stub, "s"), "S05");
+        assert_eq!((stub.emulator.program_counter, stub.emulator.steps), (3, 1));
+        assert_eq!(reply(&mut stub, "s2"), "S05");
+        assert_eq!((stub.emulator.program_counter, stub.emulator.steps), (3, 2));
+    }
+
+    #[test]
+    fn reports_errors_as_signals() {
+        let mut stub = stub(YotType::Y8, &[get_opcode(&InstructionKind::Drop)]);
+        assert_eq!(reply(&mut stub, "c"), format!("S{:02x}", SIGSEGV));
+    }
+
+    #[test]
+    fn sends_the_target_description_in_chunks() {
+        for yot_type in YOT_TYPES {
+            let mut stub = stub(yot_type, &[0x00]);
+            let description = target_description(yot_type as usize * 8);
+            let mut received: String = String::new();
+            loop {
+                let chunk = reply(&mut stub, &format!("qXfer:features:read:target.xml:{:x},20", received.len()));
+                let (more, data) = chunk.split_at(1);
+                received.push_str(data);
+                if more == "l" {
+                    break;
+                }
+                assert_eq!((more, data.len()), ("m", 0x20));
+            }
+            assert_eq!(received, description);
+            assert!(received.contains(&format!("bitsize=\"{}\"", yot_type as usize * 8)));
+            // reading past the end
+            assert_eq!(reply(&mut stub, &format!("qXfer:features:read:target.xml:{:x},20", description.len() + 1)), "l");
+            assert_eq!(reply(&mut stub, "qXfer:features:read:target.xml:0"), "E01");
+        }
+    }
+
+    #[test]
+    fn reads_memory_up_to_the_packet_size() {
+        let mut stub = stub(YotType::Y8, &[0x00]);
+        assert!(stub.query("qSupported:multiprocess+").starts_with("PacketSize=4000;"));
+        assert_eq!(stub.reply("m0,3"), Some("400200".to_owned()));
+        assert_eq!(stub.reply("m0,2000").map(|reply| reply.len()), Some(PACKET_SIZE));
+        assert_eq!(stub.reply("m0,2001"), Some("E01".to_owned()));
+        assert_eq!(stub.reply("m0,ffffffffffffffff"), Some("E01".to_owned()));
+    }
+}
@@ -1,3 +1,8 @@
+/// Pushes the byte following it.
+pub const PUSH_OPCODE: u8 = 0x20;
+/// Pops an address, pushes the address of the next instruction and jumps.
+pub const SUBROUTINE_JUMP_OPCODE: u8 = 0x72;
+
 #[derive(Debug, Copy, Clone)]
 pub enum InstructionKind {
     Break,
@@ -87,3 +92,143 @@ pub fn get_opcode(instruction_kind: &InstructionKind) -> u8 {
         InstructionKind::NotEqual => 0xd1,
     }
 }
+
+pub fn get_instruction_kind_by_opcode(opcode: u8) -> Option<InstructionKind> {
+    match opcode {
+        0x00 => Some(InstructionKind::Break),
+        0x10 => Some(InstructionKind::NoOperation),
+        0x30 => Some(InstructionKind::Drop),
+        0x40 => Some(InstructionKind::Switch),
+        0x41 => Some(InstructionKind::Pick),
+        0x42 => Some(InstructionKind::Paste),
+        0x50 => Some(InstructionKind::Roll),
+        0x51 => Some(InstructionKind::Insert),
+        0x60 => Some(InstructionKind::FetchMemory),
+        0x61 => Some(InstructionKind::StoreMemory),
+        0x70 => Some(InstructionKind::Jump),
+        0x71 => Some(InstructionKind::Branch),
+        0x80 => Some(InstructionKind::SetInterruptFlag),
+        0x81 => Some(InstructionKind::SetInterruptAddress),
+        0x90 => Some(InstructionKind::Add),
+        0x91 => Some(InstructionKind::Subtract),
+        0xa0 => Some(InstructionKind::AddWithCarry),
+        0xa1 => Some(InstructionKind::SubtractWithBorrow),
+        0xb0 => Some(InstructionKind::And),
+        0xb1 => Some(InstructionKind::InclusiveOr),
+        0xb2 => Some(InstructionKind::ExclusiveOr),
+        0xc0 => Some(InstructionKind::ShiftLeft),
+        0xc1 => Some(InstructionKind::ShiftRight),
+        0xd0 => Some(InstructionKind::Equal),
+        0xd1 => Some(InstructionKind::NotEqual),
+        _ => None,
+    }
+}
+
+pub fn get_mnemonic(instruction_kind: &InstructionKind) -> &'static str {
+    match instruction_kind {
+        InstructionKind::Break => "brk",
+        InstructionKind::NoOperation => "nop",
+        InstructionKind::Drop => "drp",
+        InstructionKind::Switch => "swc",
+        InstructionKind::Pick => "pck",
+        InstructionKind::Paste => "pst",
+        InstructionKind::Roll => "rol",
+        InstructionKind::Insert => "ins",
+        InstructionKind::FetchMemory => "fcm",
+        InstructionKind::StoreMemory => "stm",
+        InstructionKind::Jump => "jmp",
+        InstructionKind::Branch => "bnc",
+        InstructionKind::SetInterruptFlag => "sif",
+        InstructionKind::SetInterruptAddress => "sia",
+        InstructionKind::Add => "add",
+        InstructionKind::Subtract => "sub",
+        InstructionKind::AddWithCarry => "adc",
+        InstructionKind::SubtractWithBorrow => "sbb",
+        InstructionKind::And => "and",
+        InstructionKind::InclusiveOr => "ior",
+        InstructionKind::ExclusiveOr => "xor",
+        InstructionKind::ShiftLeft => "shl",
+        InstructionKind::ShiftRight => "shr",
+        InstructionKind::Equal => "equ",
+        InstructionKind::NotEqual => "neq",
+    }
+}
+
+/// Reads an address stored from the most significant byte down, as in the
+/// header of a binary and the operands of the pushes of a label address.
+pub fn read_address(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |address, byte| (address << 8) | *byte as u64)
+}
+
+/// Evaluates an instruction that pops two bytes and pushes one without any
+/// side effects, or returns `None` for any other kind of instruction. Stack
+/// cells are single bytes on every Yot type, so results wrap around at 8 bits.
+pub fn evaluate_pure(instruction_kind: &InstructionKind, a: u8, b: u8) -> Option<u8> {
+    match instruction_kind {
+        InstructionKind::Add => Some(a.wrapping_add(b)),
+        InstructionKind::Subtract => Some(a.wrapping_sub(b)),
+        InstructionKind::And => Some(a & b),
+        InstructionKind::InclusiveOr => Some(a | b),
+        InstructionKind::ExclusiveOr => Some(a ^ b),
+        InstructionKind::ShiftLeft => Some(a.checked_shl(b as u32).unwrap_or(0)),
+        InstructionKind::ShiftRight => Some(a.checked_shr(b as u32).unwrap_or(0)),
+        InstructionKind::Equal => Some((a == b) as u8),
+        InstructionKind::NotEqual => Some((a != b) as u8),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(mnemonic: &str, a: u8, b: u8) -> Option<u8> {
+        evaluate_pure(&get_instruction_kind(mnemonic).unwrap(), a, b)
+    }
+
+    #[test]
+    fn arithmetic_wraps_around_at_8_bits() {
+        assert_eq!(evaluate("add", 0x12, 0x34), Some(0x46));
+        assert_eq!(evaluate("add", 0xff, 0x01), Some(0x00));
+        assert_eq!(evaluate("add", 0xff, 0xff), Some(0xfe));
+        assert_eq!(evaluate("sub", 0x34, 0x12), Some(0x22));
+        assert_eq!(evaluate("sub", 0x00, 0x01), Some(0xff));
+        assert_eq!(evaluate("sub", 0x12, 0x34), Some(0xde));
+    }
+
+    #[test]
+    fn shifts_drop_the_bits_shifted_out() {
+        assert_eq!(evaluate("shl", 0x81, 0x01), Some(0x02));
+        assert_eq!(evaluate("shl", 0x01, 0x07), Some(0x80));
+        assert_eq!(evaluate("shr", 0x81, 0x01), Some(0x40));
+        assert_eq!(evaluate("shr", 0x80, 0x07), Some(0x01));
+        assert_eq!(evaluate("shl", 0x2a, 0x00), Some(0x2a));
+        assert_eq!(evaluate("shr", 0x2a, 0x00), Some(0x2a));
+    }
+
+    #[test]
+    fn shifts_by_8_bits_or_more_clear_the_byte() {
+        for b in [0x08, 0x09, 0x20, 0x80, 0xff] {
+            assert_eq!(evaluate("shl", 0xff, b), Some(0x00));
+            assert_eq!(evaluate("shr", 0xff, b), Some(0x00));
+        }
+    }
+
+    #[test]
+    fn evaluates_bitwise_and_comparison_instructions() {
+        assert_eq!(evaluate("and", 0xf0, 0x3c), Some(0x30));
+        assert_eq!(evaluate("ior", 0xf0, 0x3c), Some(0xfc));
+        assert_eq!(evaluate("xor", 0xf0, 0x3c), Some(0xcc));
+        assert_eq!(evaluate("equ", 0x2a, 0x2a), Some(0x01));
+        assert_eq!(evaluate("equ", 0x2a, 0x2b), Some(0x00));
+        assert_eq!(evaluate("neq", 0x2a, 0x2a), Some(0x00));
+        assert_eq!(evaluate("neq", 0x2a, 0x2b), Some(0x01));
+    }
+
+    #[test]
+    fn leaves_instructions_depending_on_the_carry_or_with_side_effects() {
+        for mnemonic in ["adc", "sbb", "brk", "nop", "drp", "swc", "pck", "pst", "rol", "ins", "fcm", "stm", "jmp", "bnc", "sif", "sia"] {
+            assert_eq!(evaluate(mnemonic, 0x01, 0x02), None, "{}", mnemonic);
+        }
+    }
+}
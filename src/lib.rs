@@ -0,0 +1,25 @@
+use span::Span;
+use tokenizer::token::Token;
+use argument_parser::YotType;
+use emitter::Symbol;
+use instruction::{InstructionKind, PUSH_OPCODE, SUBROUTINE_JUMP_OPCODE, evaluate_pure, get_instruction_kind, get_instruction_kind_by_opcode, get_mnemonic, get_opcode, read_address};
+
+pub mod argument_parser;
+pub mod coverage;
+pub mod debug_info;
+pub mod debugger;
+pub mod disassembler;
+pub mod emitter;
+pub mod emulator;
+pub mod gdb_stub;
+pub mod instruction;
+pub mod listing;
+pub mod map;
+pub mod profiler;
+pub mod reader;
+pub mod reporter;
+pub mod span;
+pub mod stack_check;
+pub mod test_runner;
+pub mod tokenizer;
+pub mod writer;
@@ -0,0 +1,138 @@
+use codespan_reporting::files::{Files, SimpleFiles};
+use std::fmt::Write;
+use super::emitter::{Assembly, Fragment};
+use super::YotType;
+
+const BYTES_PER_ROW: usize = 8;
+
+struct Row<'a> {
+    address: Option<usize>,
+    bytes: &'a [u8],
+    line_number: Option<usize>,
+    text: &'a str,
+}
+
+fn write_row(output: &mut String, row: Row, yot_type: YotType) {
+    let address_width = yot_type as usize * 2;
+    let address = match row.address {
+        Some(address) => format!("{:0width$x}", address, width = address_width),
+        None => " ".repeat(address_width),
+    };
+    let bytes: Vec<String> = row.bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let line_number = match row.line_number {
+        Some(line_number) => line_number.to_string(),
+        None => String::new(),
+    };
+    let row = format!("{}  {:<width$}  {:>5}  {}", address, bytes.join(" "), line_number, row.text, width = BYTES_PER_ROW * 3 - 1);
+    let _ = writeln!(output, "{}", row.trim_end());
+}
+
+/// Writes rows for the given bytes starting at `address`, breaking them up
+/// into rows of at most `BYTES_PER_ROW` bytes. Only the first row carries the
+/// line number and the text.
+fn write_bytes(output: &mut String, address: usize, bytes: &[u8], line_number: Option<usize>, text: &str, yot_type: YotType) {
+    let mut chunks = bytes.chunks(BYTES_PER_ROW).peekable();
+    if chunks.peek().is_none() {
+        write_row(output, Row { address: None, bytes: &[], line_number, text }, yot_type);
+    }
+    for (i, chunk) in chunks.enumerate() {
+        write_row(output, Row {
+            address: Some(address + i * BYTES_PER_ROW),
+            bytes: chunk,
+            line_number: if i == 0 { line_number } else { None },
+            text: if i == 0 { text } else { "" },
+        }, yot_type);
+    }
+}
+
+fn line_index(files: &SimpleFiles<String, &str>, fragment: &Fragment) -> Option<usize> {
+    fragment.span.and_then(|span| files.line_index(span.file_id, span.from.offset).ok())
+}
+
+/// Renders an assembly listing of the source file: every source line is
+/// printed next to the address and the bytes it was assembled to, with
+/// label definitions marked on rows of their own. The header and any
+/// padding, which have no source lines, are listed before and after it.
+pub fn list(
+    assembly: &Assembly,
+    yot_type: YotType,
+    files: &SimpleFiles<String, &str>,
+    file_id: usize,
+) -> String {
+    let mut output: String = String::new();
+    let source: &str = files.source(file_id).unwrap_or("");
+    let line_count = files.line_index(file_id, source.len()).map(|index| index + 1).unwrap_or(0);
+    let mut fragments = assembly.fragments.iter().peekable();
+    let mut end: usize = 0;
+
+    while let Some(fragment) = fragments.next_if(|fragment| fragment.span.is_none()) {
+        let bytes = &assembly.binary[fragment.address..fragment.address + fragment.size];
+        write_bytes(&mut output, fragment.address, bytes, None, "; header", yot_type);
+        end = fragment.address + fragment.size;
+    }
+
+    for line in 0..line_count {
+        let text = files.line_range(file_id, line)
+            .map(|range| source[range].trim_end_matches(&['\r', '\n'][..]))
+            .unwrap_or("");
+        let mut start: Option<usize> = None;
+
+        while let Some(fragment) = fragments.next_if(|fragment| matches!(line_index(files, fragment), Some(index) if index <= line)) {
+            if let Some(label) = &fragment.label {
+                write_row(&mut output, Row {
+                    address: Some(fragment.address),
+                    bytes: &[],
+                    line_number: None,
+                    text: &format!("<{}>", label),
+                }, yot_type);
+            } else if start.is_none() {
+                start = Some(fragment.address);
+            }
+            end = fragment.address + fragment.size;
+        }
+
+        match start {
+            Some(start) => write_bytes(&mut output, start, &assembly.binary[start..end], Some(line + 1), text, yot_type),
+            None => write_row(&mut output, Row { address: None, bytes: &[], line_number: Some(line + 1), text }, yot_type),
+        }
+    }
+
+    if end < assembly.binary.len() {
+        write_row(&mut output, Row {
+            address: Some(end),
+            bytes: &[],
+            line_number: None,
+            text: &format!("; padding ({} bytes)", assembly.binary.len() - end),
+        }, yot_type);
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::emitter::{Optimizations, emit};
+    use super::super::tokenizer::tokenize;
+
+    #[test]
+    fn lists_addresses_at_the_width_of_the_yot_type() {
+        let source = "#01 @done bnc\n:done\n@done @done drp drp\nbrk\n";
+        let mut files = SimpleFiles::new();
+        let file_id = files.add("test.ysm".to_owned(), source);
+        let tokens = tokenize(source, file_id).unwrap();
+        let assembly = emit(&tokens, YotType::Y16, 0x80, None, &Optimizations::none()).unwrap();
+        // two-byte addresses, pushed as two data literals each
+        assert_eq!(list(&assembly, YotType::Y16, &files, file_id), "\
+0000  00 80                           ; header
+0002  00 04                           ; header
+0004  20 01 20 00 20 0b 71         1  #01 @done bnc
+000b                                  <done>
+                                   2  :done
+000b  20 00 20 0b 20 00 20 0b      3  @done @done drp drp
+0013  30 30
+0015  00                           4  brk
+                                   5
+");
+    }
+}
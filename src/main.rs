@@ -1,34 +1,58 @@
-use structopt::StructOpt;
-use span::{Span, Spanned};
-use tokenizer::token::Token;
-use reporter::{Reporter, Report};
-use argument_parser::{Config, YotType};
-use instruction::{InstructionKind, get_instruction_kind, get_opcode};
-
-mod argument_parser;
-mod emitter;
-mod error;
-mod instruction;
-mod reader;
-mod reporter;
-mod span;
-mod tokenizer;
-mod writer;
-
-fn main() {
-    let config = Config::from_args();
+use std::fs;
+use std::net;
+use std::process::ExitCode;
+use std::io::{self, BufRead, Write};
+use ysm::argument_parser::{AssembleConfig, CheckConfig, Config, DebugConfig, DeviceKind, GdbConfig, InterruptConfig, DisassembleConfig, MapFormat, ProfileConfig, RunConfig, TestConfig};
+use ysm::reporter::{Diagnostic, Label, LabelStyle, Reporter, Report};
+use ysm::span::Spanned;
+use ysm::tokenizer::token::Token;
+use ysm::debug_info::DebugInfo;
+use ysm::debugger::{self, Command, Debugger, Stop};
+use ysm::{coverage, disassembler, emitter, emulator, gdb_stub, listing, map, profiler, reader, stack_check, test_runner, tokenizer, writer};
+
+fn main() -> ExitCode {
+    match Config::from_command_line() {
+        Config::Assemble(config) => assemble(config),
+        Config::Disassemble(config) => disassemble(config),
+        Config::Run(config) => run(config),
+        Config::Debug(config) => debug(config),
+        Config::Check(config) => check(config),
+        Config::Test(config) => test(config),
+        Config::Profile(config) => profile(config),
+        Config::Gdb(config) => gdb(config),
+    }
+}
+
+fn assemble(config: AssembleConfig) -> ExitCode {
     let mut reporter = Reporter::new();
 
+    let mut formats = writer::Formats::new();
+    formats.register(Box::new(writer::IntelHex { record_length: config.record_length }));
+    formats.register(Box::new(writer::SRecord { record_length: config.record_length }));
+    formats.register(Box::new(writer::CArray { array_name: config.array_name.clone() }));
+    formats.register(Box::new(writer::RustArray { array_name: config.array_name.clone() }));
+
+    let format = match formats.get(&config.output_format) {
+        Some(format) => format,
+        None => {
+            writer::Error::OutputFormatUnknown {
+                name: config.output_format.clone(),
+                known_names: formats.names(),
+            }.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    };
+
     let source_contents = match reader::read(&config.source_path) {
         Ok(source_contents) => source_contents,
         Err(err) => {
             err.report(&reporter);
-            return;
+            return ExitCode::FAILURE;
         }
     };
 
     let file_id = reporter.add_file(
-        config.source_path,
+        reader::source_name(&config.source_path),
         &source_contents,
     );
 
@@ -38,30 +62,638 @@ fn main() {
             for err in errs.iter() {
                 err.report(&reporter);
             }
-            return;
+            return ExitCode::FAILURE;
         }
     };
 
-    let binary: Vec<u8> = match emitter::emit(
+    let (tokens, tail_calls) = if config.tail_calls {
+        emitter::tail_call::optimize(&tokens, config.yot_type)
+    } else {
+        (tokens, Vec::new())
+    };
+
+    let assembly: emitter::Assembly = match emitter::emit(
         &tokens,
         config.yot_type,
         config.initial_stack_pointer,
         config.exact_binary_size,
+        &emitter::Optimizations {
+            peephole: config.peephole,
+            fold_constants: config.fold_constants,
+            strip_dead_code: config.strip_dead_code,
+            kept_labels: config.kept_labels,
+        },
     ) {
+        Ok(assembly) => assembly,
+        Err(errs) => {
+            for err in errs.iter() {
+                err.report(&reporter);
+            }
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if config.verbose {
+        for tail_call in tail_calls.iter() {
+            reporter.note(Diagnostic {
+                message: format!("subroutine jump to `{}` followed by a return turned into a tail call", tail_call.label),
+                labels: vec![Label {
+                    style: LabelStyle::Primary,
+                    span: tail_call.span,
+                    message: format!("rewritten to `@{} jmp`", tail_call.label),
+                }],
+            });
+        }
+        for removed_block in assembly.removed_blocks.iter() {
+            reporter.note(Diagnostic {
+                message: format!(
+                    "removed unreachable label `{}` ({} byte(s))",
+                    removed_block.label, removed_block.size,
+                ),
+                labels: vec![],
+            });
+        }
+        for rewrite in assembly.rewrites.iter() {
+            reporter.note(Diagnostic {
+                message: format!(
+                    "optimization `{}` applied {} time(s), saving {} byte(s)",
+                    rewrite.rule, rewrite.count, rewrite.saved_bytes,
+                ),
+                labels: vec![],
+            });
+        }
+    }
+
+    if let Some(listing_path) = &config.listing_path {
+        let listing = listing::list(&assembly, config.yot_type, &reporter.files, file_id);
+        if let Err(err) = writer::write_text(listing_path, &listing) {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let Some(map_path) = &config.map_path {
+        let map = match config.map_format {
+            MapFormat::Text => map::text(&assembly.symbols, config.yot_type, &reporter.files),
+            MapFormat::Json => map::json(&assembly.symbols, config.yot_type, assembly.entry_point, &reporter.files),
+        };
+        if let Err(err) = writer::write_text(map_path, &map) {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let Some(debug_info_path) = &config.debug_info_path {
+        let debug_info = DebugInfo::new(&assembly, config.yot_type, &reporter.files);
+        if let Err(err) = writer::write_text(debug_info_path, &debug_info.to_string()) {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let Some(bank_size) = config.bank_size {
+        let banks = writer::Banks {
+            size: bank_size,
+            fill_byte: config.fill_byte,
+            name_template: &config.bank_name_template,
+            pinned_labels: &config.pinned_labels,
+        };
+        if let Err(errs) = writer::write_banks(&config.output_path, &assembly.binary, &assembly.symbols, &banks) {
+            for err in errs.iter() {
+                err.report(&reporter);
+            }
+            return ExitCode::FAILURE;
+        }
+        return ExitCode::SUCCESS;
+    }
+
+    let image = writer::Image {
+        binary: &assembly.binary,
+        symbols: &assembly.symbols,
+        entry_point: assembly.entry_point,
+        yot_type: config.yot_type,
+    };
+
+    if let Err(err) = writer::write(&config.output_path, &image, format, config.force) {
+        err.report(&reporter);
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+fn disassemble(config: DisassembleConfig) -> ExitCode {
+    let reporter = Reporter::new();
+
+    let binary = match reader::read_binary(&config.binary_path) {
         Ok(binary) => binary,
+        Err(err) => {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut names: Vec<(u64, String)> = Vec::new();
+    if let Some(map_path) = &config.map_path {
+        let map = match reader::read(map_path) {
+            Ok(map) => map,
+            Err(err) => {
+                err.report(&reporter);
+                return ExitCode::FAILURE;
+            }
+        };
+        names = match disassembler::parse_map(&map) {
+            Ok(names) => names,
+            Err(err) => {
+                err.report(&reporter);
+                return ExitCode::FAILURE;
+            }
+        };
+    }
+
+    let disassembly = disassembler::disassemble(&binary, config.yot_type, &names);
+
+    if let Err(err) = writer::write_text(&config.output_path, &disassembly.to_string()) {
+        err.report(&reporter);
+        return ExitCode::FAILURE;
+    }
+
+    match disassembler::verify(&disassembly, &binary) {
+        Ok(()) => ExitCode::SUCCESS,
+        // the disassembly is still all that can be recovered from such a
+        // binary, the warning only says it won't reassemble
+        Err(err @ disassembler::Error::HeaderMissing) | Err(err @ disassembler::Error::BytesUnknown { .. }) => {
+            err.report(&reporter);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            err.report(&reporter);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(config: RunConfig) -> ExitCode {
+    let reporter = Reporter::new();
+
+    let binary = match reader::read_binary(&config.binary_path) {
+        Ok(binary) => binary,
+        Err(err) => {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut emulator = match emulator::Emulator::new(&binary, config.yot_type) {
+        Ok(emulator) => emulator,
+        Err(err) => {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for device_config in config.devices.iter() {
+        let device: Box<dyn emulator::Device> = match device_config.kind {
+            DeviceKind::ConsoleOutput => match &config.console_output_path {
+                Some(path) => match fs::File::create(path) {
+                    Ok(file) => Box::new(emulator::ConsoleOutput::new(Box::new(file))),
+                    Err(io_error) => {
+                        writer::Error::CouldNotWriteFile { file_path: path.clone(), io_error }.report(&reporter);
+                        return ExitCode::FAILURE;
+                    }
+                },
+                None => Box::new(emulator::ConsoleOutput::new(Box::new(io::stdout()))),
+            },
+            DeviceKind::ConsoleInput => match &config.console_input_path {
+                Some(path) => match fs::File::open(path) {
+                    Ok(file) => Box::new(emulator::ConsoleInput::new(Box::new(file))),
+                    Err(io_error) => {
+                        reader::Error::CouldNotReadFile { file_path: path.clone(), io_error }.report(&reporter);
+                        return ExitCode::FAILURE;
+                    }
+                },
+                None => Box::new(emulator::ConsoleInput::new(Box::new(io::stdin()))),
+            },
+            DeviceKind::CycleCounter => Box::new(emulator::CycleCounter::new()),
+        };
+        if let Err(err) = emulator.devices.map(device_config.address, device) {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    for interrupt_config in config.interrupts.iter() {
+        match interrupt_config {
+            InterruptConfig::At(steps) => emulator.interrupts.schedule(*steps),
+            InterruptConfig::Every(period) => emulator.interrupts.every(*period),
+            InterruptConfig::Device => emulator.interrupts.devices = true,
+        }
+    }
+
+    let result = emulator.run_with(config.max_steps, |emulator| {
+        let step = emulator.step()?;
+        let message = match step {
+            emulator::Step::Interrupted { return_address } => format!(
+                "interrupt after {} instruction(s), entered the handler at {:#x} from {:#x}",
+                emulator.steps, emulator.program_counter, return_address,
+            ),
+            emulator::Step::Returned { return_address } => format!(
+                "after {} instruction(s), returned from the interrupt handler to {:#x}",
+                emulator.steps, return_address,
+            ),
+            emulator::Step::Continued | emulator::Step::Halted => return Ok(step),
+        };
+        if config.trace_interrupts {
+            reporter.note(Diagnostic { message, labels: vec![] });
+        }
+        Ok(step)
+    });
+    if let Err(err) = result {
+        err.report(&reporter);
+        return ExitCode::FAILURE;
+    }
+
+    let stack: Vec<String> = emulator.stack().iter().map(|byte| format!("{:02x}", byte)).collect();
+    println!(
+        "halted at {:#0width$x} after {} instruction(s)",
+        emulator.program_counter, emulator.steps, width = config.yot_type as usize * 2 + 2,
+    );
+    println!("stack: {}", stack.join(" "));
+    println!("memory:");
+    print!("{}", emulator.memory.dump());
+    ExitCode::SUCCESS
+}
+
+/// Reports any errors from tokenizing a source file.
+fn report_tokens(reporter: &Reporter, tokens: Result<Vec<Spanned<Token>>, Vec<tokenizer::Error>>) -> Option<Vec<Spanned<Token>>> {
+    match tokens {
+        Ok(tokens) => Some(tokens),
         Err(errs) => {
             for err in errs.iter() {
+                err.report(reporter);
+            }
+            None
+        }
+    }
+}
+
+/// Tokenizes and assembles a source file without any optimizations,
+/// reporting any errors.
+fn emit_source(
+    reporter: &Reporter,
+    source_contents: &str,
+    file_id: usize,
+    yot_type: ysm::argument_parser::YotType,
+    initial_stack_pointer: u64,
+) -> Option<emitter::Assembly> {
+    let tokens = report_tokens(reporter, tokenizer::tokenize(source_contents, file_id))?;
+    emit_tokens(reporter, &tokens, yot_type, initial_stack_pointer)
+}
+
+/// Assembles tokens without any optimizations, reporting any errors.
+fn emit_tokens(
+    reporter: &Reporter,
+    tokens: &[Spanned<Token>],
+    yot_type: ysm::argument_parser::YotType,
+    initial_stack_pointer: u64,
+) -> Option<emitter::Assembly> {
+
+    match emitter::emit(tokens, yot_type, initial_stack_pointer, None, &emitter::Optimizations::none()) {
+        Ok(assembly) => Some(assembly),
+        Err(errs) => {
+            for err in errs.iter() {
+                err.report(reporter);
+            }
+            None
+        }
+    }
+}
+
+fn report_location(reporter: &Reporter, debugger: &Debugger, message: String) {
+    reporter.note(Diagnostic {
+        message,
+        labels: debugger.span(debugger.emulator.program_counter).into_iter().map(|span| Label {
+            style: LabelStyle::Primary,
+            span,
+            message: "next instruction".to_owned(),
+        }).collect(),
+    });
+}
+
+fn report_stop(reporter: &Reporter, debugger: &Debugger, stop: &Stop) {
+    let program_counter = debugger.emulator.program_counter;
+    let message = match stop {
+        Stop::Stepped => format!("stepped to {:#x}", program_counter),
+        Stop::Breakpoint => format!("breakpoint at {:#x}", program_counter),
+        Stop::Watchpoint { address, old, new } => format!(
+            "byte at {:#x} changed from {:#04x} to {:#04x}, stopped at {:#x}",
+            address, old, new, program_counter,
+        ),
+        Stop::Halted => format!("halted on `brk` at {:#x} after {} instruction(s)", program_counter, debugger.emulator.steps),
+    };
+    report_location(reporter, debugger, message);
+}
+
+fn debug(config: DebugConfig) -> ExitCode {
+    let mut reporter = Reporter::new();
+
+    let source_contents = match reader::read(&config.source_path) {
+        Ok(source_contents) => source_contents,
+        Err(err) => {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let file_id = reporter.add_file(reader::source_name(&config.source_path), &source_contents);
+
+    let assembly = match emit_source(&reporter, &source_contents, file_id, config.yot_type, config.initial_stack_pointer) {
+        Some(assembly) => assembly,
+        None => return ExitCode::FAILURE,
+    };
+
+    let mut debugger = match Debugger::new(assembly, config.yot_type, &reporter.files) {
+        Ok(debugger) => debugger,
+        Err(err) => {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let width = config.yot_type as usize * 2;
+    report_location(&reporter, &debugger, format!("entry point at {:#x}", debugger.emulator.program_counter));
+
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        print!("(ysm) ");
+        let _ = io::stdout().flush();
+        line.clear();
+        if !matches!(stdin.lock().read_line(&mut line), Ok(read) if read > 0) {
+            break;
+        }
+
+        let command = match debugger::parse(&line) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(err) => {
                 err.report(&reporter);
+                continue;
+            }
+        };
+
+        let stopped = match command {
+            Command::Break(location) => debugger.set_breakpoint(&location)
+                .map(|address| println!("breakpoint set at {:#0w$x}", address, w = width + 2)),
+            Command::Clear(location) => debugger.clear_breakpoint(&location)
+                .map(|address| println!("breakpoint cleared at {:#0w$x}", address, w = width + 2)),
+            Command::Watch(address) => {
+                debugger.watch(address);
+                Ok(())
+            }
+            Command::Unwatch(address) => {
+                debugger.unwatch(address);
+                Ok(())
+            }
+            Command::Step(count) => {
+                let mut stepped = Ok(Stop::Stepped);
+                for _ in 0..count {
+                    stepped = debugger.step();
+                    if !matches!(stepped, Ok(Stop::Stepped)) {
+                        break;
+                    }
+                }
+                stepped.map(|stop| report_stop(&reporter, &debugger, &stop))
+            }
+            Command::Next => debugger.step_over().map(|stop| report_stop(&reporter, &debugger, &stop)),
+            Command::Continue => debugger.resume().map(|stop| report_stop(&reporter, &debugger, &stop)),
+            Command::Stack => {
+                let stack: Vec<String> = debugger.emulator.stack().iter().map(|byte| format!("{:02x}", byte)).collect();
+                println!("{}", stack.join(" "));
+                Ok(())
             }
-            return;
+            Command::Memory { address, length } => {
+                for row in (0..length as u64).step_by(16) {
+                    let bytes: Vec<String> = (row..(row + 16).min(length as u64))
+                        .map(|offset| format!("{:02x}", debugger.emulator.memory.read(address.wrapping_add(offset))))
+                        .collect();
+                    println!("{:0w$x}  {}", debugger.emulator.memory.wrap(address.wrapping_add(row)), bytes.join(" "), w = width);
+                }
+                Ok(())
+            }
+            Command::Where => {
+                report_location(&reporter, &debugger, format!("at {:#x}", debugger.emulator.program_counter));
+                Ok(())
+            }
+            Command::Help => {
+                println!("{}", debugger::HELP);
+                Ok(())
+            }
+            Command::Quit => break,
+        };
+
+        if let Err(err) = stopped {
+            err.report(&reporter);
+            if !matches!(err, debugger::Error::Emulator(_)) {
+                continue;
+            }
+            report_location(&reporter, &debugger, format!("stopped at {:#x}", debugger.emulator.program_counter));
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn check(config: CheckConfig) -> ExitCode {
+    let mut reporter = Reporter::new();
+
+    let source_contents = match reader::read(&config.source_path) {
+        Ok(source_contents) => source_contents,
+        Err(err) => {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let file_id = reporter.add_file(reader::source_name(&config.source_path), &source_contents);
+
+    let tokens = match report_tokens(&reporter, tokenizer::tokenize_stack_assertions(&source_contents, file_id)) {
+        Some(tokens) => tokens,
+        None => return ExitCode::FAILURE,
+    };
+    let assembly = match emit_tokens(&reporter, &tokens, config.yot_type, config.initial_stack_pointer) {
+        Some(assembly) => assembly,
+        None => return ExitCode::FAILURE,
+    };
+
+    match stack_check::check(&assembly, config.yot_type, config.max_steps) {
+        Ok(count) => {
+            reporter.note(Diagnostic {
+                message: format!("{} stack assertion(s) hold", count),
+                labels: vec![],
+            });
+            ExitCode::SUCCESS
+        }
+        Err(errs) => {
+            for err in errs.iter() {
+                err.report(&reporter);
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn test(config: TestConfig) -> ExitCode {
+    let mut reporter = Reporter::new();
+
+    let source_contents = match reader::read(&config.source_path) {
+        Ok(source_contents) => source_contents,
+        Err(err) => {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let file_id = reporter.add_file(reader::source_name(&config.source_path), &source_contents);
+
+    let tokens = match report_tokens(&reporter, tokenizer::tokenize_tests(&source_contents, file_id)) {
+        Some(tokens) => tokens,
+        None => return ExitCode::FAILURE,
+    };
+    // the code under test has to assemble on its own, so that failures to
+    // assemble a test case are down to the test case
+    if emit_source(&reporter, &source_contents, file_id, config.yot_type, config.initial_stack_pointer).is_none() {
+        return ExitCode::FAILURE;
+    }
+
+    let mut coverage = coverage::Coverage::new();
+    let outcomes = test_runner::run_tests(&tokens, config.yot_type, config.initial_stack_pointer, config.max_steps, &mut coverage);
+    if outcomes.is_empty() {
+        reporter.note(Diagnostic {
+            message: "no test cases found".to_owned(),
+            labels: vec![],
+        });
+        return ExitCode::SUCCESS;
+    }
+
+    for outcome in outcomes.iter() {
+        println!("test {} ... {}", outcome.label, if outcome.result.is_ok() { "ok" } else { "FAILED" });
+    }
+    let failed: Vec<&test_runner::Error> = outcomes.iter().filter_map(|outcome| outcome.result.as_ref().err()).collect();
+    for err in failed.iter() {
+        err.report(&reporter);
+    }
+    println!("test result: {} passed, {} failed", outcomes.len() - failed.len(), failed.len());
+
+    if let Some(coverage_path) = &config.coverage_path {
+        if let Err(err) = writer::write_text(coverage_path, &coverage.listing(&reporter.files, file_id)) {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    }
+    if let Some(lcov_path) = &config.lcov_path {
+        if let Err(err) = writer::write_text(lcov_path, &coverage.lcov(&reporter.files)) {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    }
+    if failed.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn profile(config: ProfileConfig) -> ExitCode {
+    let mut reporter = Reporter::new();
+
+    let costs_contents = match config.costs_path.as_ref().map(|costs_path| reader::read(costs_path)).transpose() {
+        Ok(costs_contents) => costs_contents,
+        Err(err) => {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    };
+    let costs = match costs_contents.as_deref().map(profiler::Costs::parse).transpose() {
+        Ok(costs) => costs.unwrap_or_default(),
+        Err(err) => {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
         }
     };
 
-    match writer::write(&config.output_path, &binary) {
-        Ok(()) => (),
+    let source_contents = match reader::read(&config.source_path) {
+        Ok(source_contents) => source_contents,
         Err(err) => {
             err.report(&reporter);
-            return;
+            return ExitCode::FAILURE;
         }
+    };
+
+    let file_id = reporter.add_file(reader::source_name(&config.source_path), &source_contents);
+
+    let assembly = match emit_source(&reporter, &source_contents, file_id, config.yot_type, config.initial_stack_pointer) {
+        Some(assembly) => assembly,
+        None => return ExitCode::FAILURE,
+    };
+
+    let profile = match profiler::profile(&assembly, config.yot_type, &costs, config.max_steps) {
+        Ok(profile) => profile,
+        Err(err) => {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    print!("{}", profile);
+    if let Some(folded_path) = &config.folded_path {
+        if let Err(err) = writer::write_text(folded_path, &profile.folded()) {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn gdb(config: GdbConfig) -> ExitCode {
+    let reporter = Reporter::new();
+
+    let binary = match reader::read_binary(&config.binary_path) {
+        Ok(binary) => binary,
+        Err(err) => {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let emulator = match emulator::Emulator::new(&binary, config.yot_type) {
+        Ok(emulator) => emulator,
+        Err(err) => {
+            err.report(&reporter);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = match config.port {
+        Some(port) => {
+            let address = format!("127.0.0.1:{}", port);
+            let stream = net::TcpListener::bind(&address)
+                .and_then(|listener| {
+                    reporter.note(Diagnostic {
+                        message: format!("waiting for GDB on {}, connect with `target remote {}`", address, address),
+                        labels: vec![],
+                    });
+                    listener.accept()
+                })
+                .and_then(|(stream, _)| Ok((stream.try_clone()?, stream)));
+            match stream {
+                Ok((input, output)) => gdb_stub::Stub::new(emulator, input, output).serve(),
+                Err(io_error) => Err(gdb_stub::Error::CouldNotListen { address, io_error }),
+            }
+        }
+        None => gdb_stub::Stub::new(emulator, io::stdin(), io::stdout()).serve(),
+    };
+    if let Err(err) = result {
+        err.report(&reporter);
+        return ExitCode::FAILURE;
     }
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,128 @@
+use codespan_reporting::files::{Files, SimpleFiles};
+use std::fmt::Write;
+use super::{Symbol, YotType};
+
+struct Location {
+    file: String,
+    line: usize,
+}
+
+fn locate(files: &SimpleFiles<String, &str>, symbol: &Symbol) -> Location {
+    let file_id = symbol.span.file_id;
+    Location {
+        file: files.name(file_id).unwrap_or_default(),
+        line: files.line_number(file_id, files.line_index(file_id, symbol.span.from.offset).unwrap_or(0)).unwrap_or(0),
+    }
+}
+
+/// Renders the label map in a plain-text format of one label per line with
+/// its hexadecimal address, the size of the block it delimits in bytes, its
+/// name and the file and line it is defined at, separated by whitespace.
+/// Lines starting with `;` are comments.
+pub fn text(symbols: &[Symbol], yot_type: YotType, files: &SimpleFiles<String, &str>) -> String {
+    let address_width = yot_type as usize * 2;
+    let label_width = symbols.iter().map(|symbol| symbol.label.len()).max().unwrap_or(0);
+    let mut output: String = String::new();
+
+    let _ = writeln!(output, "; {} label map", yot_type);
+    let _ = writeln!(output, "; address, size, label, location");
+    for symbol in symbols.iter() {
+        let location = locate(files, symbol);
+        let _ = writeln!(
+            output,
+            "{:0aw$x}  {:>8}  {:<lw$}  {}:{}",
+            symbol.address, symbol.size, symbol.label, location.file, location.line,
+            aw = address_width, lw = label_width,
+        );
+    }
+
+    output
+}
+
+fn json_string(string: &str) -> String {
+    let mut escaped: String = String::with_capacity(string.len() + 2);
+    escaped.push('"');
+    for c in string.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+/// Renders the label map as a JSON object holding the Yot type, the entry
+/// point and an array of labels with their addresses, block sizes and
+/// definition sites. Addresses and sizes are plain numbers.
+pub fn json(symbols: &[Symbol], yot_type: YotType, entry_point: usize, files: &SimpleFiles<String, &str>) -> String {
+    let mut output: String = String::new();
+
+    let _ = writeln!(output, "{{");
+    let _ = writeln!(output, "  \"yot_type\": {},", json_string(&yot_type.to_string()));
+    let _ = writeln!(output, "  \"entry_point\": {},", entry_point);
+    let _ = writeln!(output, "  \"labels\": [");
+    for (i, symbol) in symbols.iter().enumerate() {
+        let location = locate(files, symbol);
+        let _ = writeln!(
+            output,
+            "    {{ \"label\": {}, \"address\": {}, \"size\": {}, \"file\": {}, \"line\": {} }}{}",
+            json_string(&symbol.label), symbol.address, symbol.size, json_string(&location.file), location.line,
+            if i + 1 < symbols.len() { "," } else { "" },
+        );
+    }
+    let _ = writeln!(output, "  ]");
+    let _ = writeln!(output, "}}");
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::emitter::{Optimizations, emit};
+    use super::super::tokenizer::tokenize;
+
+    const SOURCE: &str = "#01 add\n:start\nnop\n:loop\n@loop jmp\n";
+
+    fn symbols(file_name: &str, yot_type: YotType) -> (Vec<Symbol>, usize, SimpleFiles<String, &'static str>) {
+        let mut files = SimpleFiles::new();
+        let file_id = files.add(file_name.to_owned(), SOURCE);
+        let tokens = tokenize(SOURCE, file_id).unwrap();
+        let assembly = emit(&tokens, yot_type, 0x80, None, &Optimizations::none()).unwrap();
+        (assembly.symbols, assembly.entry_point, files)
+    }
+
+    #[test]
+    fn renders_a_line_per_label() {
+        let (symbols, _, files) = symbols("test.ysm", YotType::Y16);
+        assert_eq!(text(&symbols, YotType::Y16, &files), "\
+; yot-16 label map
+; address, size, label, location
+0007         1  start  test.ysm:2
+0008         5  loop   test.ysm:4
+");
+    }
+
+    #[test]
+    fn renders_json_with_escaped_strings() {
+        let (symbols, entry_point, files) = symbols("a \"b\"\\c\td\u{1}.ysm", YotType::Y8);
+        assert_eq!(json(&symbols, YotType::Y8, entry_point, &files), r#"{
+  "yot_type": "yot-8",
+  "entry_point": 2,
+  "labels": [
+    { "label": "start", "address": 5, "size": 1, "file": "a \"b\"\\c\td\u0001.ysm", "line": 2 },
+    { "label": "loop", "address": 6, "size": 3, "file": "a \"b\"\\c\td\u0001.ysm", "line": 4 }
+  ]
+}
+"#);
+        assert_eq!(json(&[], YotType::Y8, entry_point, &files), "{\n  \"yot_type\": \"yot-8\",\n  \"entry_point\": 2,\n  \"labels\": [\n  ]\n}\n");
+    }
+}
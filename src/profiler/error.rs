@@ -0,0 +1,40 @@
+use std::fmt;
+use crate::emulator;
+use crate::reporter::{Diagnostic, Report, Reporter};
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    CostTableMalformed { line_number: usize },
+    Emulator(emulator::Error),
+}
+
+impl From<emulator::Error> for Error {
+    fn from(error: emulator::Error) -> Error {
+        Error::Emulator(error)
+    }
+}
+
+impl Report for Error {
+    fn report(&self, r: &Reporter) {
+        match &self {
+            Error::CostTableMalformed { .. } => r.write(Diagnostic {
+                message: self.to_string(),
+                labels: vec![],
+            }),
+            Error::Emulator(error) => error.report(r),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self {
+            Error::CostTableMalformed { line_number } => write!(f,
+                "malformed cost table on line {}", line_number,
+            ),
+            Error::Emulator(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
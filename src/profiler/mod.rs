@@ -0,0 +1,244 @@
+pub use error::Error;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use super::emitter::Assembly;
+use super::emulator::{Emulator, Step};
+use super::{SUBROUTINE_JUMP_OPCODE, YotType, get_instruction_kind, get_opcode};
+
+mod error;
+
+/// The name instructions outside of any label are attributed to.
+const UNLABELLED: &str = "[unlabelled]";
+
+/// The cost of executing each opcode, e.g. in cycles on a target.
+pub struct Costs {
+    costs: [u64; 256],
+}
+
+impl Costs {
+    /// Every opcode costs 1, so that costs count instructions.
+    pub fn new() -> Costs {
+        Costs { costs: [1; 256] }
+    }
+
+    /// Parses a cost table of one opcode per line, given as a mnemonic or
+    /// as a hexadecimal opcode prefixed with `0x` like `0x20` for pushes,
+    /// followed by its cost. Opcodes that are not listed cost 1 and lines
+    /// starting with `;` are comments.
+    pub fn parse(table: &str) -> Result<Costs, Error> {
+        let mut costs = Costs::new();
+        for (i, line) in table.lines().enumerate() {
+            if line.trim().is_empty() || line.starts_with(';') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let opcode = fields.next().and_then(|opcode| match opcode.strip_prefix("0x") {
+                Some(opcode) => u8::from_str_radix(opcode, 16).ok(),
+                None => get_instruction_kind(opcode).map(|instruction_kind| get_opcode(&instruction_kind)),
+            });
+            match (opcode, fields.next().map(|cost| cost.parse::<u64>()), fields.next()) {
+                (Some(opcode), Some(Ok(cost)), None) => costs.costs[opcode as usize] = cost,
+                _ => return Err(Error::CostTableMalformed { line_number: i + 1 }),
+            }
+        }
+        Ok(costs)
+    }
+}
+
+impl Default for Costs {
+    fn default() -> Costs {
+        Costs::new()
+    }
+}
+
+/// What was spent in the code following a single label, up to the next one.
+pub struct Entry {
+    pub label: String,
+    pub instructions: u64,
+    pub cost: u64,
+}
+
+pub struct Profile {
+    /// Sorted by cost, the most expensive first.
+    pub entries: Vec<Entry>,
+    /// The cost spent in every chain of subroutine calls.
+    pub stacks: BTreeMap<String, u64>,
+    pub instructions: u64,
+    pub cost: u64,
+}
+
+struct Frame {
+    label: String,
+    return_address: u64,
+}
+
+/// The label of every address that is defined at, the first label defined
+/// there if there are several.
+fn labels(assembly: &Assembly) -> BTreeMap<u64, &str> {
+    let mut labels: BTreeMap<u64, &str> = BTreeMap::new();
+    for symbol in assembly.symbols.iter() {
+        labels.entry(symbol.address as u64).or_insert(&symbol.label);
+    }
+    labels
+}
+
+fn enclosing_label<'a>(labels: &BTreeMap<u64, &'a str>, address: u64) -> &'a str {
+    labels.range(..=address).next_back().map_or(UNLABELLED, |(_, label)| label)
+}
+
+/// Runs the program until it reaches `brk` and attributes the cost of every
+/// executed instruction to the closest label at or before it.
+///
+/// Subroutine calls are followed by the subroutine jumps into them and the
+/// returns to the instruction after each jump, so the stacks only include
+/// subroutines entered with a subroutine jump.
+pub fn profile(assembly: &Assembly, yot_type: YotType, costs: &Costs, max_steps: Option<u64>) -> Result<Profile, Error> {
+    let labels = labels(assembly);
+    let mut emulator = Emulator::new(&assembly.binary, yot_type)?;
+    let mut entries: BTreeMap<&str, Entry> = BTreeMap::new();
+    let mut stacks: BTreeMap<String, u64> = BTreeMap::new();
+    let mut frames: Vec<Frame> = vec![Frame {
+        label: enclosing_label(&labels, emulator.program_counter).to_owned(),
+        return_address: u64::MAX,
+    }];
+    let mut total_cost: u64 = 0;
+
+    emulator.run_with(max_steps, |emulator| {
+        let address = emulator.program_counter;
+        let opcode = emulator.memory.read(address);
+        let cost = costs.costs[opcode as usize];
+        let label = enclosing_label(&labels, address);
+
+        let entry = entries.entry(label).or_insert_with(|| Entry { label: label.to_owned(), instructions: 0, cost: 0 });
+        entry.instructions += 1;
+        entry.cost += cost;
+        total_cost += cost;
+
+        let mut stack: Vec<&str> = frames.iter().map(|frame| frame.label.as_str()).collect();
+        if stack.last() != Some(&label) {
+            stack.push(label);
+        }
+        *stacks.entry(stack.join(";")).or_default() += cost;
+
+        let step = emulator.step()?;
+        if step == Step::Halted {
+            return Ok(step);
+        }
+        if opcode == SUBROUTINE_JUMP_OPCODE {
+            frames.push(Frame {
+                label: enclosing_label(&labels, emulator.program_counter).to_owned(),
+                return_address: emulator.memory.wrap(address.wrapping_add(1)),
+            });
+        } else if let Some(i) = frames.iter().rposition(|frame| frame.return_address == emulator.program_counter) {
+            frames.truncate(i);
+        }
+        Ok(step)
+    })?;
+
+    let mut entries: Vec<Entry> = entries.into_values().collect();
+    entries.sort_by(|a, b| b.cost.cmp(&a.cost).then(b.instructions.cmp(&a.instructions)));
+    Ok(Profile { entries, stacks, instructions: emulator.steps, cost: total_cost })
+}
+
+impl Profile {
+    /// Renders the stacks in the folded format read by flame graph tools, one
+    /// stack per line with its frames separated by `;`, followed by its cost.
+    pub fn folded(&self) -> String {
+        let mut output: String = String::new();
+        for (stack, cost) in self.stacks.iter() {
+            let _ = writeln!(output, "{} {}", stack, cost);
+        }
+        output
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label_width = self.entries.iter().map(|entry| entry.label.len()).max().unwrap_or(0).max(5);
+        writeln!(f, "{:<lw$}  {:>12}  {:>12}  {:>6}", "label", "instructions", "cost", "%", lw = label_width)?;
+        for entry in self.entries.iter() {
+            let share = if self.cost == 0 { 0.0 } else { entry.cost as f64 * 100.0 / self.cost as f64 };
+            writeln!(
+                f,
+                "{:<lw$}  {:>12}  {:>12}  {:>6.2}",
+                entry.label, entry.instructions, entry.cost, share, lw = label_width,
+            )?;
+        }
+        writeln!(f, "{:<lw$}  {:>12}  {:>12}  {:>6.2}", "total", self.instructions, self.cost, 100.0, lw = label_width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::emitter::{Optimizations, emit};
+    use super::super::tokenizer::tokenize;
+    use super::super::InstructionKind;
+
+    const NESTED: &str = "
+        outer
+        brk
+        :outer
+        inner
+        jmp
+        :inner
+        nop nop nop
+        jmp
+    ";
+
+    fn run(source: &str, costs: &Costs) -> Profile {
+        let tokens = tokenize(source, 0).unwrap();
+        let assembly = emit(&tokens, YotType::Y8, 0x80, None, &Optimizations::none()).unwrap();
+        profile(&assembly, YotType::Y8, costs, None).unwrap()
+    }
+
+    fn entry(profile: &Profile, label: &str) -> (u64, u64) {
+        let entry = profile.entries.iter().find(|entry| entry.label == label).unwrap();
+        (entry.instructions, entry.cost)
+    }
+
+    #[test]
+    fn attributes_instructions_to_the_closest_label_before_them() {
+        let profile = run(NESTED, &Costs::new());
+        // push and subroutine jump, then brk
+        assert_eq!(entry(&profile, UNLABELLED), (3, 3));
+        assert_eq!(entry(&profile, "outer"), (3, 3));
+        assert_eq!(entry(&profile, "inner"), (4, 4));
+        assert_eq!((profile.instructions, profile.cost), (10, 10));
+    }
+
+    #[test]
+    fn sorts_entries_by_cost() {
+        let costs = Costs::parse("jmp 10\nnop 0\n").unwrap();
+        let profile = run(NESTED, &costs);
+        let entries: Vec<(&str, u64, u64)> = profile.entries.iter()
+            .map(|entry| (entry.label.as_str(), entry.instructions, entry.cost))
+            .collect();
+        assert_eq!(entries, [("outer", 3, 12), ("inner", 4, 10), (UNLABELLED, 3, 3)]);
+    }
+
+    #[test]
+    fn folds_stacks_of_nested_subroutine_jumps() {
+        let profile = run(NESTED, &Costs::new());
+        assert_eq!(
+            profile.folded(),
+            format!("{u} 3\n{u};outer 3\n{u};outer;inner 4\n", u = UNLABELLED),
+        );
+    }
+
+    #[test]
+    fn parses_cost_tables() {
+        let costs = Costs::parse("; cycles\nadd 3\n\n0x20 2\n").unwrap();
+        assert_eq!(costs.costs[get_opcode(&InstructionKind::Add) as usize], 3);
+        assert_eq!(costs.costs[0x20], 2);
+        assert_eq!(costs.costs[get_opcode(&InstructionKind::Subtract) as usize], 1);
+    }
+
+    #[test]
+    fn rejects_malformed_cost_tables() {
+        for table in ["add 3\nmul 2\n", "add\n", "add 3 4\n", "0xzz 1\n", "add -1\n"] {
+            let line_number = if table.starts_with("add 3\n") { 2 } else { 1 };
+            assert_eq!(Costs::parse(table).err(), Some(Error::CostTableMalformed { line_number }), "{:?}", table);
+        }
+    }
+}
@@ -1,10 +1,50 @@
 pub use error::Error;
 use std::fs;
-use std::path::Path;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
 
 mod error;
 
-pub(super) fn read(path: &Path) -> Result<String, Error> {
-    fs::read_to_string(path)
-        .map_err(|io_error| Error::CouldNotReadFile { file_path: path.to_path_buf(), io_error })
+const STANDARD_INPUT_NAME: &str = "<stdin>";
+
+fn is_standard_input(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Returns the name the source is reported under, which is its path unless
+/// it is read from the standard input.
+pub fn source_name(path: &Path) -> PathBuf {
+    if is_standard_input(path) {
+        PathBuf::from(STANDARD_INPUT_NAME)
+    } else {
+        path.to_path_buf()
+    }
+}
+
+/// Reads the source file at the given path, or the standard input if the
+/// path is `-`.
+pub fn read(path: &Path) -> Result<String, Error> {
+    if is_standard_input(path) {
+        let mut source_contents: String = String::new();
+        io::stdin().read_to_string(&mut source_contents)
+            .map_err(|io_error| Error::CouldNotReadFile { file_path: source_name(path), io_error })?;
+        Ok(source_contents)
+    } else {
+        fs::read_to_string(path)
+            .map_err(|io_error| Error::CouldNotReadFile { file_path: path.to_path_buf(), io_error })
+    }
+}
+
+/// Reads the binary file at the given path, or the standard input if the
+/// path is `-`.
+pub fn read_binary(path: &Path) -> Result<Vec<u8>, Error> {
+    if is_standard_input(path) {
+        let mut binary: Vec<u8> = Vec::new();
+        io::stdin().read_to_end(&mut binary)
+            .map_err(|io_error| Error::CouldNotReadFile { file_path: source_name(path), io_error })?;
+        Ok(binary)
+    } else {
+        fs::read(path)
+            .map_err(|io_error| Error::CouldNotReadFile { file_path: path.to_path_buf(), io_error })
+    }
 }
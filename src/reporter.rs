@@ -31,7 +31,19 @@ impl<'a> Reporter<'a> {
     }
 
     pub fn write(&self, diagnostic: Diagnostic) {
-        let codespan_diagnostic = codespan_reporting::diagnostic::Diagnostic::error()
+        self.emit(codespan_reporting::diagnostic::Diagnostic::error(), diagnostic);
+    }
+
+    pub fn warn(&self, diagnostic: Diagnostic) {
+        self.emit(codespan_reporting::diagnostic::Diagnostic::warning(), diagnostic);
+    }
+
+    pub fn note(&self, diagnostic: Diagnostic) {
+        self.emit(codespan_reporting::diagnostic::Diagnostic::note(), diagnostic);
+    }
+
+    fn emit(&self, codespan_diagnostic: codespan_reporting::diagnostic::Diagnostic<usize>, diagnostic: Diagnostic) {
+        let codespan_diagnostic = codespan_diagnostic
             .with_message(diagnostic.message)
             .with_labels(diagnostic.labels.iter().map(|label: &Label| -> codespan_reporting::diagnostic::Label<usize> {
                 match label.style {
@@ -54,6 +66,12 @@ impl<'a> Reporter<'a> {
     }
 }
 
+impl<'a> Default for Reporter<'a> {
+    fn default() -> Reporter<'a> {
+        Reporter::new()
+    }
+}
+
 pub struct Diagnostic {
     pub message: String,
     pub labels: Vec<Label>,
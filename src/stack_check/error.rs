@@ -0,0 +1,55 @@
+use super::Span;
+use std::fmt;
+use crate::emulator;
+use crate::reporter::{Diagnostic, Label, LabelStyle, Report, Reporter};
+
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    StackMismatch {
+        span: Span,
+        expected: String,
+        actual: String,
+    },
+    AssertionUnreached {
+        span: Span,
+    },
+    Emulator(emulator::Error),
+}
+
+impl From<emulator::Error> for Error {
+    fn from(error: emulator::Error) -> Error {
+        Error::Emulator(error)
+    }
+}
+
+impl Report for Error {
+    fn report(&self, r: &Reporter) {
+        match &self {
+            Error::StackMismatch { span, expected, actual } => r.write(Diagnostic {
+                message: "stack assertion failed".to_owned(),
+                labels: vec![Label {
+                    style: LabelStyle::Primary,
+                    span: *span,
+                    message: format!("expected `{}`, found `{}`", expected, actual),
+                }],
+            }),
+            Error::AssertionUnreached { span } => r.write(Diagnostic {
+                message: "stack assertion never reached".to_owned(),
+                labels: vec![Label {
+                    style: LabelStyle::Primary,
+                    span: *span,
+                    message: String::new(),
+                }],
+            }),
+            Error::Emulator(error) => error.report(r),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "")
+    }
+}
+
+impl std::error::Error for Error {}
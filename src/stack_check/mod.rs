@@ -0,0 +1,170 @@
+pub use error::Error;
+use std::collections::HashMap;
+use super::emitter::Assembly;
+use super::emulator::Emulator;
+use super::tokenizer::token::{Cell, StackPattern};
+use super::{Span, YotType};
+
+mod error;
+
+fn cell_size(cell: &Cell, yot_type: YotType) -> usize {
+    match cell {
+        Cell::Byte(_) | Cell::AnyByte => 1,
+        Cell::AnyAddress => yot_type as usize,
+    }
+}
+
+fn pattern_size(pattern: &StackPattern, yot_type: YotType) -> usize {
+    pattern.cells.iter().map(|cell| cell_size(cell, yot_type)).sum()
+}
+
+/// Matches the data stack, from the bottom up, against the pattern.
+pub fn matches(pattern: &StackPattern, stack: &[u8], yot_type: YotType) -> bool {
+    let size = pattern_size(pattern, yot_type);
+    if stack.len() < size || (!pattern.rest && stack.len() != size) {
+        return false;
+    }
+    let mut bytes = stack[stack.len() - size..].iter();
+    pattern.cells.iter().all(|cell| match cell {
+        Cell::Byte(byte) => bytes.next() == Some(byte),
+        Cell::AnyByte => bytes.next().is_some(),
+        Cell::AnyAddress => bytes.nth(yot_type as usize - 1).is_some(),
+    })
+}
+
+/// Renders the part of the stack the pattern was matched against.
+pub fn render_stack(pattern: &StackPattern, stack: &[u8], yot_type: YotType) -> String {
+    let size = pattern_size(pattern, yot_type);
+    let mut words: Vec<String> = Vec::new();
+    let shown = if pattern.rest && stack.len() > size {
+        words.push("..".to_owned());
+        &stack[stack.len() - size..]
+    } else {
+        stack
+    };
+    words.extend(shown.iter().map(|byte| format!("{:02x}", byte)));
+    words.join(" ")
+}
+
+/// Runs the program until it reaches `brk` and checks every stack assertion
+/// each time execution reaches it. Each assertion is reported once, on its
+/// first mismatch, and assertions that are never reached are reported as
+/// well. Returns the number of assertions checked.
+pub fn check(assembly: &Assembly, yot_type: YotType, max_steps: u64) -> Result<usize, Vec<Error>> {
+    let mut emulator = Emulator::new(&assembly.binary, yot_type).map_err(|err| vec![err.into()])?;
+    let mut stack_assertions: HashMap<u64, Vec<usize>> = HashMap::new();
+    for (i, stack_assertion) in assembly.stack_assertions.iter().enumerate() {
+        stack_assertions.entry(stack_assertion.address as u64).or_default().push(i);
+    }
+
+    let mut errors: Vec<Error> = Vec::new();
+    let mut reached: Vec<bool> = vec![false; assembly.stack_assertions.len()];
+    let mut failed: Vec<bool> = vec![false; assembly.stack_assertions.len()];
+
+    let result = emulator.run_with(Some(max_steps), |emulator| {
+        for i in stack_assertions.get(&emulator.program_counter).into_iter().flatten() {
+            let stack_assertion = &assembly.stack_assertions[*i];
+            reached[*i] = true;
+            let stack = emulator.stack();
+            if !failed[*i] && !matches(&stack_assertion.pattern, &stack, yot_type) {
+                failed[*i] = true;
+                errors.push(Error::StackMismatch {
+                    span: stack_assertion.span,
+                    expected: stack_assertion.pattern.to_string(),
+                    actual: render_stack(&stack_assertion.pattern, &stack, yot_type),
+                });
+            }
+        }
+        emulator.step()
+    });
+    if let Err(err) = result {
+        errors.push(err.into());
+    }
+
+    let unreached: Vec<Span> = assembly.stack_assertions.iter().zip(reached.iter())
+        .filter(|(_, reached)| !**reached)
+        .map(|(stack_assertion, _)| stack_assertion.span)
+        .collect();
+    errors.extend(unreached.into_iter().map(|span| Error::AssertionUnreached { span }));
+
+    if errors.is_empty() {
+        Ok(assembly.stack_assertions.len())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::emitter::{Optimizations, emit};
+    use super::super::tokenizer::tokenize_stack_assertions;
+
+    fn check_source(source: &str, yot_type: YotType) -> Result<usize, Vec<Error>> {
+        let tokens = tokenize_stack_assertions(source, 0).unwrap();
+        let assembly = emit(&tokens, yot_type, 0x80, None, &Optimizations::none()).unwrap();
+        check(&assembly, yot_type, 1000)
+    }
+
+    #[test]
+    fn counts_assertions_that_hold() {
+        let source = "
+            ;=
+            #03 #06
+            ;= 03 06
+            add
+            ;= 09
+            brk
+        ";
+        assert_eq!(check_source(source, YotType::Y8), Ok(3));
+    }
+
+    #[test]
+    fn reports_each_failing_assertion_once_with_both_stacks() {
+        let source = "
+            #03
+            :loop
+            ;= 03 _
+            #01 @loop bnc
+            brk
+        ";
+        match check_source(source, YotType::Y8).unwrap_err().as_slice() {
+            [Error::StackMismatch { expected, actual, .. }, Error::Emulator(_)] => {
+                assert_eq!(expected, "03 _");
+                assert_eq!(actual, "03");
+            }
+            errs => panic!("unexpected errors {:?}", errs),
+        }
+    }
+
+    #[test]
+    fn reports_assertions_that_are_never_reached() {
+        let source = "
+            #03
+            ;= 03
+            brk
+            ;= 03
+            nop
+        ";
+        assert!(matches!(check_source(source, YotType::Y8).unwrap_err().as_slice(), [Error::AssertionUnreached { .. }]));
+    }
+
+    #[test]
+    fn matches_wildcards_by_their_size() {
+        for yot_type in [YotType::Y8, YotType::Y16, YotType::Y32, YotType::Y64] {
+            let source = "
+                #01 #02 #03
+                ;= .. 02 _
+                callee
+                ;= 01 _ _
+                brk
+                :callee
+                ;= .. 03 retaddr
+                jmp
+            ";
+            assert_eq!(check_source(source, yot_type), Ok(3), "{}", yot_type);
+            let holds = check_source("#01 callee brk\n:callee\n;= 01 _\njmp\n", yot_type).is_ok();
+            assert_eq!(holds, yot_type == YotType::Y8, "{}", yot_type);
+        }
+    }
+}
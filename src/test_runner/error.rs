@@ -0,0 +1,72 @@
+use super::Span;
+use std::fmt;
+use crate::{emitter, emulator};
+use crate::reporter::{Diagnostic, Label, LabelStyle, Report, Reporter};
+
+#[derive(Debug)]
+pub enum Error {
+    StackMismatch {
+        span: Span,
+        expected: String,
+        actual: String,
+    },
+    MemoryMismatch {
+        span: Span,
+        address: u64,
+        expected: Vec<u8>,
+        actual: Vec<u8>,
+    },
+    Emulator {
+        span: Span,
+        error: emulator::Error,
+    },
+    Assembly(Vec<emitter::Error>),
+}
+
+fn render_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<String>>().join(" ")
+}
+
+impl Report for Error {
+    fn report(&self, r: &Reporter) {
+        match &self {
+            Error::StackMismatch { span, expected, actual } => r.write(Diagnostic {
+                message: "test failed, unexpected stack".to_owned(),
+                labels: vec![Label {
+                    style: LabelStyle::Primary,
+                    span: *span,
+                    message: format!("expected `{}`, found `{}`", expected, actual),
+                }],
+            }),
+            Error::MemoryMismatch { span, address, expected, actual } => r.write(Diagnostic {
+                message: format!("test failed, unexpected memory at address {:#x}", address),
+                labels: vec![Label {
+                    style: LabelStyle::Primary,
+                    span: *span,
+                    message: format!("expected `{}`, found `{}`", render_bytes(expected), render_bytes(actual)),
+                }],
+            }),
+            Error::Emulator { span, error } => r.write(Diagnostic {
+                message: "test failed".to_owned(),
+                labels: vec![Label {
+                    style: LabelStyle::Primary,
+                    span: *span,
+                    message: error.to_string(),
+                }],
+            }),
+            Error::Assembly(errors) => {
+                for error in errors.iter() {
+                    error.report(r);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "")
+    }
+}
+
+impl std::error::Error for Error {}
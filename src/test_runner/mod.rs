@@ -0,0 +1,174 @@
+pub use error::Error;
+use super::coverage::{Coverage, Trace};
+use super::emitter::{self, Assembly, Optimizations};
+use super::emulator::Emulator;
+use super::span::{Spanned, Spanning};
+use super::stack_check::{matches, render_stack};
+use super::tokenizer::token::TestCase;
+use super::{InstructionKind, Span, Token, YotType};
+
+mod error;
+
+/// The result of running a single test case.
+pub struct Outcome {
+    pub label: String,
+    pub span: Span,
+    pub result: Result<(), Error>,
+}
+
+/// Assembles the test case together with the code under test. The program
+/// starts with a harness that pushes the initial stack, jumps to the
+/// subroutine under test and stops at `brk` once it returns. The harness is
+/// left out of the fragments, so that it doesn't count towards coverage.
+fn assemble(
+    test_case: &TestCase,
+    span: Span,
+    tokens: &[Spanned<Token>],
+    yot_type: YotType,
+    initial_stack_pointer: u64,
+) -> Result<Assembly, Error> {
+    let mut harness: Vec<Spanned<Token>> = Vec::with_capacity(tokens.len() + 3);
+    if !test_case.initial_stack.is_empty() {
+        harness.push(Token::DataLiteral(test_case.initial_stack.clone()).spanning(span));
+    }
+    harness.push(Token::SubroutineJump(test_case.label.clone()).spanning(span));
+    harness.push(Token::PrimitiveInstruction(InstructionKind::Break).spanning(span));
+    harness.extend(tokens.iter().cloned());
+
+    emitter::emit(&harness, yot_type, initial_stack_pointer, None, &Optimizations::none())
+        .map(|mut assembly| {
+            assembly.fragments.retain(|fragment| fragment.span != Some(span));
+            assembly
+        })
+        .map_err(Error::Assembly)
+}
+
+fn run(
+    test_case: &TestCase,
+    span: Span,
+    tokens: &[Spanned<Token>],
+    yot_type: YotType,
+    initial_stack_pointer: u64,
+    max_steps: u64,
+    coverage: &mut Coverage,
+) -> Result<(), Error> {
+    let assembly = assemble(test_case, span, tokens, yot_type, initial_stack_pointer)?;
+    let emulation_failed = |error| Error::Emulator { span, error };
+    let mut emulator = Emulator::new(&assembly.binary, yot_type).map_err(emulation_failed)?;
+    let mut trace = Trace::new();
+    let result = trace.run(&mut emulator, Some(max_steps));
+    coverage.add(&assembly, &trace);
+    result.map_err(emulation_failed)?;
+
+    let stack = emulator.stack();
+    if !matches(&test_case.expected_stack, &stack, yot_type) {
+        return Err(Error::StackMismatch {
+            span,
+            expected: test_case.expected_stack.to_string(),
+            actual: render_stack(&test_case.expected_stack, &stack, yot_type),
+        });
+    }
+    for (address, expected) in test_case.expected_memory.iter() {
+        let actual: Vec<u8> = (0..expected.len() as u64)
+            .map(|i| emulator.memory.read(emulator.memory.wrap(address.wrapping_add(i))))
+            .collect();
+        if actual != *expected {
+            return Err(Error::MemoryMismatch { span, address: *address, expected: expected.clone(), actual });
+        }
+    }
+    Ok(())
+}
+
+/// Runs every `;test` case in the tokens, each in a fresh emulator that
+/// fails once it has executed `max_steps` instructions, and adds what each of
+/// them executed to the coverage.
+pub fn run_tests(
+    tokens: &[Spanned<Token>],
+    yot_type: YotType,
+    initial_stack_pointer: u64,
+    max_steps: u64,
+    coverage: &mut Coverage,
+) -> Vec<Outcome> {
+    tokens.iter()
+        .filter_map(|token| match token {
+            Spanned { node: Token::TestCase(test_case), span } => Some((test_case, *span)),
+            _ => None,
+        })
+        .map(|(test_case, span)| Outcome {
+            label: test_case.label.clone(),
+            span,
+            result: run(test_case, span, tokens, yot_type, initial_stack_pointer, max_steps, coverage),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::emulator;
+    use super::super::tokenizer::tokenize_tests;
+
+    const SUBROUTINES: &str = "
+        brk
+        :double
+        #01 swc #00 pck add #01 swc jmp
+        :store
+        #01 swc #70 stm jmp
+        :forever
+        @forever jmp
+    ";
+
+    fn run_test(test_case: &str) -> Result<(), Error> {
+        let source = format!("{}\n{}", test_case, SUBROUTINES);
+        let tokens = tokenize_tests(&source, 0).unwrap();
+        let mut outcomes = run_tests(&tokens, YotType::Y8, 0x80, 100, &mut Coverage::new());
+        assert_eq!(outcomes.len(), 1);
+        let outcome = outcomes.pop().unwrap();
+        assert_eq!(outcome.label, test_case.split_whitespace().nth(1).unwrap().trim_end_matches(':'));
+        outcome.result
+    }
+
+    #[test]
+    fn passes_when_the_stack_and_memory_match() {
+        assert!(run_test(";test double: 15 -> 2a").is_ok());
+        assert!(run_test(";test double: 07 15 -> 07 2a").is_ok());
+        assert!(run_test(";test store: 2a -> @70 2a").is_ok());
+    }
+
+    #[test]
+    fn fails_when_the_stack_does_not_match() {
+        match run_test(";test double: 15 -> 2b") {
+            Err(Error::StackMismatch { expected, actual, .. }) => assert_eq!((expected.as_str(), actual.as_str()), ("2b", "2a")),
+            result => panic!("{:?}", result),
+        }
+        assert!(matches!(run_test(";test double: 15 -> 2a 2a"), Err(Error::StackMismatch { .. })));
+    }
+
+    #[test]
+    fn fails_when_memory_does_not_match() {
+        match run_test(";test store: 2a -> @6f 00 2b") {
+            Err(Error::MemoryMismatch { address, expected, actual, .. }) => {
+                assert_eq!((address, expected, actual), (0x6f, vec![0x00, 0x2b], vec![0x00, 0x2a]));
+            }
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn fails_to_assemble_tests_of_unknown_labels() {
+        match run_test(";test triple: 15 -> 3f") {
+            Err(Error::Assembly(errors)) => {
+                assert!(matches!(errors.as_slice(), [emitter::Error::CannotFindLabel { label, .. }] if label == "triple"));
+            }
+            result => panic!("{:?}", result),
+        }
+    }
+
+    #[test]
+    fn fails_after_the_step_limit() {
+        match run_test(";test forever: -> ") {
+            Err(Error::Emulator { error: emulator::Error::StepLimitReached { steps }, .. }) => assert_eq!(steps, 100),
+            result => panic!("{:?}", result),
+        }
+    }
+}
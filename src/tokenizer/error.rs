@@ -7,6 +7,9 @@ pub enum Error {
     DigitInvalid { digit: char, span: Span },
     DigitExpected { span: Span },
     IdentifierExpected { span: Span },
+    RestMisplaced { span: Span },
+    CellInvalid { span: Span },
+    TestCaseMalformed { span: Span },
 }
 
 impl Report for Error {
@@ -36,6 +39,30 @@ impl Report for Error {
                     message: String::new(),
                 }],
             }),
+            Error::RestMisplaced { span } => r.write(Diagnostic {
+                message: "`..` can only start a stack assertion".to_owned(),
+                labels: vec![Label {
+                    style: LabelStyle::Primary,
+                    span: *span,
+                    message: String::new(),
+                }],
+            }),
+            Error::CellInvalid { span } => r.write(Diagnostic {
+                message: "invalid cell in an expected stack".to_owned(),
+                labels: vec![Label {
+                    style: LabelStyle::Primary,
+                    span: *span,
+                    message: "expected a byte as two hex digits, `_` or `retaddr`".to_owned(),
+                }],
+            }),
+            Error::TestCaseMalformed { span } => r.write(Diagnostic {
+                message: "malformed test case".to_owned(),
+                labels: vec![Label {
+                    style: LabelStyle::Primary,
+                    span: *span,
+                    message: "expected `;test <label>: <bytes> -> <stack> [@<address> <bytes>]...`".to_owned(),
+                }],
+            }),
         }
     }
 }
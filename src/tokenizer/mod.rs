@@ -1,6 +1,6 @@
 use super::span::{Location, Span, Spanned, Spanning};
 pub use error::Error;
-use token::Token;
+use token::{Cell, StackPattern, TestCase, Token};
 use unicode_segmentation::UnicodeSegmentation;
 use super::{InstructionKind, get_instruction_kind};
 
@@ -8,7 +8,7 @@ pub mod error;
 pub mod token;
 
 fn is_digit(string: &str) -> bool {
-    matches!(string.chars().next(), Some(ch) if ch.is_digit(16))
+    matches!(string.chars().next(), Some(ch) if ch.is_ascii_hexdigit())
 }
 
 fn to_digit(string: &str) -> Option<u8> {
@@ -32,7 +32,7 @@ fn scan(string: &str, file_id: usize) -> Vec<Spanned<&str>> {
         .graphemes(true)
         .scan(Location { offset: 0 }, |location, grapheme| {
             let current_location = *location;
-            (*location).offset += grapheme.chars().count();
+            location.offset += grapheme.chars().count();
             Some(Spanned {
                 node: grapheme,
                 span: Span {
@@ -45,8 +45,8 @@ fn scan(string: &str, file_id: usize) -> Vec<Spanned<&str>> {
         .collect()
 }
 
-fn parse_identifier<'a>(
-    symbols: &[Spanned<&'a str>],
+fn parse_identifier(
+    symbols: &[Spanned<&str>],
     i: &mut usize,
     skip_first: bool,
 ) -> Result<Spanned<String>, Error> {
@@ -76,8 +76,8 @@ fn parse_identifier<'a>(
     }
 }
 
-fn parse_data_literal<'a>(
-    symbols: &[Spanned<&'a str>],
+fn parse_data_literal(
+    symbols: &[Spanned<&str>],
     i: &mut usize,
 ) -> Result<Spanned<Vec<u8>>, Error> {
     let os = symbols.get(*i).unwrap().span;
@@ -111,7 +111,162 @@ fn parse_data_literal<'a>(
     }
 }
 
-pub(super) fn tokenize(input_string: &str, file_id: usize) -> Result<Vec<Spanned<Token>>, Vec<Error>> {
+fn parse_byte(word: &str) -> Option<u8> {
+    if word.len() == 2 && word.chars().all(|ch| ch.is_ascii_hexdigit()) {
+        u8::from_str_radix(word, 16).ok()
+    } else {
+        None
+    }
+}
+
+fn parse_cell(word: &Spanned<String>) -> Result<Cell, Error> {
+    match word.node.as_str() {
+        "_" => Ok(Cell::AnyByte),
+        "retaddr" => Ok(Cell::AnyAddress),
+        _ => parse_byte(&word.node).map(Cell::Byte).ok_or(Error::CellInvalid { span: word.span }),
+    }
+}
+
+/// Collects the words up to the end of the line or a `;` comment, skipping
+/// the comment.
+fn parse_words(symbols: &[Spanned<&str>], i: &mut usize) -> Vec<Spanned<String>> {
+    let mut words: Vec<Spanned<String>> = Vec::new();
+    let mut comment = false;
+    loop {
+        match symbols.get(*i) {
+            Some(Spanned { node: "\n", .. }) | Some(Spanned { node: "\r\n", .. }) | None => return words,
+            Some(Spanned { node: ";", .. }) => comment = true,
+            _ if comment => (),
+            Some(Spanned { node: c, .. }) if is_whitespace(c) => (),
+            Some(_) => {
+                words.extend(parse_identifier(symbols, i, false).ok());
+                continue;
+            }
+        }
+        *i += 1;
+    }
+}
+
+/// Parses the expected stack from the bottom up. Each word is a byte given
+/// as two hex digits, `_` for any byte or `retaddr` for any address. A
+/// leading `..` stands for whatever is below the rest.
+fn parse_stack_pattern(words: &[Spanned<String>]) -> Result<StackPattern, Error> {
+    let mut pattern = StackPattern { rest: false, cells: Vec::new() };
+    for (i, word) in words.iter().enumerate() {
+        if word.node == ".." {
+            if i > 0 {
+                return Err(Error::RestMisplaced { span: word.span });
+            }
+            pattern.rest = true;
+        } else {
+            pattern.cells.push(parse_cell(word)?);
+        }
+    }
+    Ok(pattern)
+}
+
+/// Parses a stack assertion comment, `;=` followed by the expected stack up
+/// to the end of the line.
+fn parse_stack_assertion(
+    symbols: &[Spanned<&str>],
+    i: &mut usize,
+) -> Result<Spanned<StackPattern>, Error> {
+    let os = symbols.get(*i).unwrap().span;
+    let oe = symbols.get(*i + 1).unwrap().span;
+    *i += 2;
+    let words = parse_words(symbols, i);
+    let span = Span::combine(&os, &words.last().map_or(oe, |word| word.span));
+    Ok(parse_stack_pattern(&words)?.spanning(span))
+}
+
+fn is_stack_assertion(symbols: &[Spanned<&str>], i: usize) -> bool {
+    matches!(symbols.get(i + 1), Some(Spanned { node: "=", .. }))
+        && symbols.get(i + 2).is_none_or(|Spanned { node, .. }| is_whitespace(node))
+}
+
+fn is_test_case(symbols: &[Spanned<&str>], i: usize) -> bool {
+    let directive = ["t", "e", "s", "t"];
+    directive.iter().enumerate().all(|(j, c)| matches!(symbols.get(i + 1 + j), Some(Spanned { node, .. }) if node == c))
+        && symbols.get(i + 1 + directive.len()).is_none_or(|Spanned { node, .. }| is_whitespace(node))
+}
+
+/// Parses a test case comment,
+/// `;test <label>: <bytes> -> <stack> [@<address> <bytes>]...`, where the
+/// initial stack is given as bytes and the expected stack as in a stack
+/// assertion, followed by the bytes expected in memory at any addresses.
+fn parse_test_case(
+    symbols: &[Spanned<&str>],
+    i: &mut usize,
+) -> Result<Spanned<TestCase>, Error> {
+    let os = symbols.get(*i).unwrap().span;
+    let oe = symbols.get(*i + 4).unwrap().span;
+    *i += 5;
+    let words = parse_words(symbols, i);
+    let span = Span::combine(&os, &words.last().map_or(oe, |word| word.span));
+    let malformed = || Error::TestCaseMalformed { span };
+
+    let label = match words.first() {
+        Some(Spanned { node: word, .. }) if word.len() > 1 && word.ends_with(':') => word.trim_end_matches(':').to_owned(),
+        _ => return Err(malformed()),
+    };
+    let arrow = words.iter().position(|word| word.node == "->").ok_or_else(malformed)?;
+    let mut initial_stack: Vec<u8> = Vec::new();
+    for word in words[1..arrow].iter() {
+        initial_stack.push(parse_byte(&word.node).ok_or_else(malformed)?);
+    }
+
+    let memory = words.iter().position(|word| word.node.starts_with('@')).unwrap_or(words.len());
+    if memory < arrow {
+        return Err(malformed());
+    }
+    let expected_stack = parse_stack_pattern(&words[arrow + 1..memory])?;
+    let mut expected_memory: Vec<(u64, Vec<u8>)> = Vec::new();
+    for word in words[memory..].iter() {
+        match word.node.strip_prefix('@') {
+            Some(address) => {
+                let address = u64::from_str_radix(address, 16).map_err(|_| malformed())?;
+                expected_memory.push((address, Vec::new()));
+            }
+            None => expected_memory.last_mut().unwrap().1.push(parse_byte(&word.node).ok_or_else(malformed)?),
+        }
+    }
+    if expected_memory.iter().any(|(_, bytes)| bytes.is_empty()) {
+        return Err(malformed());
+    }
+
+    Ok(TestCase { label, initial_stack, expected_stack, expected_memory }.spanning(span))
+}
+
+/// The marked comments read as tokens rather than skipped like any other
+/// comment.
+#[derive(Clone, Copy, PartialEq)]
+enum Annotations {
+    None,
+    StackAssertions,
+    TestCases,
+}
+
+/// Tokenizes a source file, treating `;=` stack assertions and `;test` cases
+/// as plain comments.
+pub fn tokenize(input_string: &str, file_id: usize) -> Result<Vec<Spanned<Token>>, Vec<Error>> {
+    tokenize_with(input_string, file_id, Annotations::None)
+}
+
+/// Tokenizes a source file along with its `;=` stack assertions, as the
+/// check subcommand does.
+pub fn tokenize_stack_assertions(input_string: &str, file_id: usize) -> Result<Vec<Spanned<Token>>, Vec<Error>> {
+    tokenize_with(input_string, file_id, Annotations::StackAssertions)
+}
+
+/// Tokenizes a source file along with its `;test` cases, as the test
+/// subcommand does.
+pub fn tokenize_tests(input_string: &str, file_id: usize) -> Result<Vec<Spanned<Token>>, Vec<Error>> {
+    tokenize_with(input_string, file_id, Annotations::TestCases)
+}
+
+fn tokenize_with(input_string: &str, file_id: usize, annotations: Annotations) -> Result<Vec<Spanned<Token>>, Vec<Error>> {
+    let stack_assertions = annotations == Annotations::StackAssertions;
+    let test_cases = annotations == Annotations::TestCases;
     let symbols = scan(input_string, file_id);
 
     let mut tokens = Vec::new();
@@ -124,6 +279,12 @@ pub(super) fn tokenize(input_string: &str, file_id: usize) -> Result<Vec<Spanned
                 Some(Spanned { node: c, .. }) if is_whitespace(c) => {
                     i += 1;
                 }
+                Some(Spanned { node: ";", .. }) if stack_assertions && is_stack_assertion(&symbols, i) => {
+                    break 'gap;
+                }
+                Some(Spanned { node: ";", .. }) if test_cases && is_test_case(&symbols, i) => {
+                    break 'gap;
+                }
                 Some(Spanned { node: ";", .. }) => {
                     i += 1;
                     let mut comment = true;
@@ -157,6 +318,18 @@ pub(super) fn tokenize(input_string: &str, file_id: usize) -> Result<Vec<Spanned
                 }
                 Err(err) => errors.push(err),
             },
+            Some(Spanned { node: ";", .. }) if test_cases && is_test_case(&symbols, i) => match parse_test_case(&symbols, &mut i) {
+                Ok(Spanned { node: tc, span }) => {
+                    tokens.push(Token::TestCase(tc).spanning(span))
+                }
+                Err(err) => errors.push(err),
+            },
+            Some(Spanned { node: ";", .. }) if stack_assertions && is_stack_assertion(&symbols, i) => match parse_stack_assertion(&symbols, &mut i) {
+                Ok(Spanned { node: sp, span }) => {
+                    tokens.push(Token::StackAssertion(sp).spanning(span))
+                }
+                Err(err) => errors.push(err),
+            },
             Some(Spanned { node: "#", .. }) => {
                 match parse_data_literal(&symbols, &mut i) {
                     Ok(Spanned { node: dl, span }) => {
@@ -185,3 +358,77 @@ pub(super) fn tokenize(input_string: &str, file_id: usize) -> Result<Vec<Spanned
         Err(errors)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_test_cases_only_when_asked_to() {
+        let source = ";test the loop\n;test mul: 03 04 -> 0c\nbrk\n";
+        let tokens = tokenize(source, 0).unwrap();
+        assert!(matches!(tokens.as_slice(), [Spanned { node: Token::PrimitiveInstruction(InstructionKind::Break), .. }]));
+
+        assert_eq!(tokenize_tests(source, 0).err().map(|errs| errs.len()), Some(1));
+        let tokens = tokenize_tests(";test mul: 03 04 -> 0c\nbrk\n", 0).unwrap();
+        match &tokens[0].node {
+            Token::TestCase(test_case) => {
+                assert_eq!(test_case.label, "mul");
+                assert_eq!(test_case.initial_stack, [0x03, 0x04]);
+            }
+            token => panic!("unexpected token {:?}", token),
+        }
+        assert_eq!(tokens.len(), 2);
+    }
+
+    #[test]
+    fn reads_stack_assertions_only_with_a_delimiter_after_the_equals_sign() {
+        let tokens = tokenize_stack_assertions(";==== init ====\n;=x\nbrk\n", 0).unwrap();
+        assert_eq!(tokens.len(), 1);
+
+        for source in [";= 01 02\nbrk\n", ";=\tretaddr\nbrk\n", ";=\nbrk\n"] {
+            let tokens = tokenize_stack_assertions(source, 0).unwrap();
+            assert!(matches!(tokens[0].node, Token::StackAssertion(_)), "{}", source);
+            assert_eq!(tokens.len(), 2, "{}", source);
+        }
+        assert!(matches!(tokenize_stack_assertions(";=", 0).unwrap()[0].node, Token::StackAssertion(_)));
+    }
+
+    fn stack_pattern(source: &str) -> Result<StackPattern, Vec<Error>> {
+        match tokenize_stack_assertions(source, 0)?.remove(0).node {
+            Token::StackAssertion(pattern) => Ok(pattern),
+            token => panic!("unexpected token {:?}", token),
+        }
+    }
+
+    #[test]
+    fn reads_stack_assertions_up_to_a_comment() {
+        let pattern = stack_pattern(";= .. 03 _ retaddr ; three, then anything\nbrk\n").unwrap();
+        assert_eq!(pattern, StackPattern {
+            rest: true,
+            cells: vec![Cell::Byte(0x03), Cell::AnyByte, Cell::AnyAddress],
+        });
+        assert_eq!(stack_pattern(";= 03;three\n").err().map(|errs| errs.len()), Some(1));
+
+        let tokens = tokenize_stack_assertions(";= 03 ; brk\nnop\n", 0).unwrap();
+        assert!(matches!(tokens[1].node, Token::PrimitiveInstruction(InstructionKind::NoOperation)));
+        assert_eq!(tokens.len(), 2);
+    }
+
+    #[test]
+    fn rejects_anything_but_bytes_and_wildcards_in_stack_assertions() {
+        for word in ["4", "004", "0304", "ret", "xy"] {
+            let source = format!(";= 03 {}\n", word);
+            assert!(matches!(stack_pattern(&source).err().as_deref(), Some([Error::CellInvalid { .. }])), "{}", word);
+        }
+        assert!(matches!(stack_pattern(";= 03 ..\n").err().as_deref(), Some([Error::RestMisplaced { .. }])));
+    }
+
+    #[test]
+    fn reads_stack_assertions_only_when_asked_to() {
+        let source = ";= 03 4\n;test mul: 03 -> x\nbrk\n";
+        assert_eq!(tokenize(source, 0).unwrap().len(), 1);
+        assert_eq!(tokenize_tests(source, 0).err().map(|errs| errs.len()), Some(1));
+        assert_eq!(tokenize_stack_assertions(source, 0).err().map(|errs| errs.len()), Some(1));
+    }
+}
@@ -1,6 +1,7 @@
 use super::{Span, Spanned, Spanning};
 use crate::impl_spanning;
 use super::InstructionKind;
+use std::fmt;
 
 #[derive(Debug, Clone)]
 pub enum Token {
@@ -9,6 +10,68 @@ pub enum Token {
     DataLiteral(Vec<u8>),
     LabelDefinition(String),
     LabelLiteral(String),
+    StackAssertion(StackPattern),
+    TestCase(TestCase),
+}
+
+/// A pattern for a single value on the data stack.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Byte(u8),
+    /// `_`, any single byte.
+    AnyByte,
+    /// `retaddr`, any address-sized value.
+    AnyAddress,
+}
+
+/// The expected data stack from the bottom up. A pattern starting with `..`
+/// only has to match the top of the stack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackPattern {
+    pub rest: bool,
+    pub cells: Vec<Cell>,
+}
+
+impl fmt::Display for StackPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut words: Vec<String> = Vec::new();
+        if self.rest {
+            words.push("..".to_owned());
+        }
+        for cell in self.cells.iter() {
+            words.push(match cell {
+                Cell::Byte(byte) => format!("{:02x}", byte),
+                Cell::AnyByte => "_".to_owned(),
+                Cell::AnyAddress => "retaddr".to_owned(),
+            });
+        }
+        write!(f, "{}", words.join(" "))
+    }
 }
 
 impl_spanning!(Token);
+
+/// A test of the subroutine at a label: the subroutine is entered with the
+/// initial stack and has to return with the expected stack and memory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestCase {
+    pub label: String,
+    pub initial_stack: Vec<u8>,
+    pub expected_stack: StackPattern,
+    pub expected_memory: Vec<(u64, Vec<u8>)>,
+}
+
+impl fmt::Display for TestCase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let initial_stack: Vec<String> = self.initial_stack.iter().map(|byte| format!("{:02x}", byte)).collect();
+        write!(f, "{}: {} -> {}", self.label, initial_stack.join(" "), self.expected_stack)?;
+        for (address, bytes) in self.expected_memory.iter() {
+            let bytes: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+            write!(f, " @{:x} {}", address, bytes.join(" "))?;
+        }
+        Ok(())
+    }
+}
+
+impl_spanning!(StackPattern);
+impl_spanning!(TestCase);
@@ -0,0 +1,85 @@
+use std::path::{Path, PathBuf};
+use super::{Error, Symbol};
+
+/// Expands the bank file name template for the bank with the given index.
+/// `{name}` is replaced by the output file stem, `{ext}` by its extension
+/// including the dot and `{bank}` by the bank index. The file is placed
+/// next to the output file.
+pub(super) fn path(output_path: &Path, template: &str, index: usize) -> PathBuf {
+    let name = output_path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let extension = output_path.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+    let file_name = template
+        .replace("{name}", &name)
+        .replace("{ext}", &extension)
+        .replace("{bank}", &index.to_string());
+    output_path.with_file_name(file_name)
+}
+
+/// Splits the binary into banks of the given size, filling up the final bank
+/// with the fill byte.
+pub(super) fn split(binary: &[u8], bank_size: usize, fill_byte: u8) -> Vec<Vec<u8>> {
+    binary.chunks(bank_size).map(|chunk| {
+        let mut bank = chunk.to_vec();
+        bank.resize(bank_size, fill_byte);
+        bank
+    }).collect()
+}
+
+/// Checks that none of the pinned labels spans across a bank boundary. Any
+/// other label may be split between banks.
+pub(super) fn check_crossings(symbols: &[Symbol], bank_size: usize, pinned_labels: &[String]) -> Result<(), Vec<Error>> {
+    let errors: Vec<Error> = symbols.iter()
+        .filter(|symbol| pinned_labels.contains(&symbol.label))
+        .filter(|symbol| symbol.size > 0 && symbol.address / bank_size != (symbol.address + symbol.size - 1) / bank_size)
+        .map(|symbol| Error::LabelCrossesBankBoundary {
+            label: symbol.label.clone(),
+            span: symbol.span,
+            start: symbol.address,
+            end: symbol.address + symbol.size,
+            boundary: (symbol.address / bank_size + 1) * bank_size,
+        })
+        .collect();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::super::span::{Location, Span};
+
+    fn symbol(label: &str, address: usize, size: usize) -> Symbol {
+        let span = Span { file_id: 0, from: Location { offset: 0 }, to: Location { offset: 0 } };
+        Symbol { label: label.to_owned(), address, size, span }
+    }
+
+    #[test]
+    fn lets_labels_cross_banks_unless_they_are_pinned() {
+        let symbols = [symbol("start", 0x00, 0x0c), symbol("mul", 0x0c, 0x08), symbol("end", 0x14, 0x01)];
+        assert!(check_crossings(&symbols, 0x10, &[]).is_ok());
+        assert!(check_crossings(&symbols, 0x10, &["start".to_owned(), "end".to_owned()]).is_ok());
+
+        let errors = check_crossings(&symbols, 0x10, &["mul".to_owned()]).unwrap_err();
+        assert!(matches!(
+            errors.as_slice(),
+            [Error::LabelCrossesBankBoundary { label, start: 0x0c, end: 0x14, boundary: 0x10, .. }] if label == "mul"
+        ));
+    }
+
+    #[test]
+    fn fills_up_the_final_bank() {
+        assert_eq!(split(&[1, 2, 3, 4, 5], 2, 0xff), [vec![1, 2], vec![3, 4], vec![5, 0xff]]);
+        assert_eq!(split(&[1, 2, 3, 4], 2, 0xff), [vec![1, 2], vec![3, 4]]);
+    }
+
+    #[test]
+    fn names_banks_after_the_template() {
+        let output_path = Path::new("out/rom.bin");
+        assert_eq!(path(output_path, "{name}.{bank}{ext}", 3), Path::new("out/rom.3.bin"));
+        assert_eq!(path(output_path, "bank{bank}.hex", 0), Path::new("out/bank0.hex"));
+    }
+}
@@ -1,11 +1,29 @@
 use std::fmt;
 use std::io;
 use std::path::PathBuf;
-use crate::reporter::{Diagnostic, Report, Reporter};
+use crate::reporter::{Diagnostic, Label, LabelStyle, Report, Reporter};
+use crate::span::Span;
 
 #[derive(Debug)]
 pub enum Error {
     CouldNotWriteFile { file_path: PathBuf, io_error: io::Error },
+    BinaryOutputToTerminal,
+    OutputFormatUnknown { name: String, known_names: Vec<String> },
+    BanksToStandardOutput,
+    LabelCrossesBankBoundary {
+        label: String,
+        span: Span,
+        start: usize,
+        end: usize,
+        boundary: usize,
+    },
+    LabelNamesCollide {
+        name: String,
+        label: String,
+        span: Span,
+        previous_label: String,
+        previous_span: Span,
+    },
 }
 
 impl Report for Error {
@@ -15,6 +33,41 @@ impl Report for Error {
                 message: format!("couldn't write {}: {}", file_path.to_string_lossy(), io_error),
                 labels: vec![],
             }),
+            Error::BinaryOutputToTerminal => r.write(Diagnostic {
+                message: "refusing to write a binary to a terminal, use --force to write it anyway".to_owned(),
+                labels: vec![],
+            }),
+            Error::OutputFormatUnknown { name, known_names } => r.write(Diagnostic {
+                message: format!("unknown output format `{}`, expected one of: {}", name, known_names.join(", ")),
+                labels: vec![],
+            }),
+            Error::BanksToStandardOutput => r.write(Diagnostic {
+                message: "can't write banks to the standard output, they need an output file name".to_owned(),
+                labels: vec![],
+            }),
+            Error::LabelCrossesBankBoundary { label, span, start, end, boundary } => r.write(Diagnostic {
+                message: format!("label `{}` crosses the bank boundary at {:#x}", label, boundary),
+                labels: vec![Label {
+                    style: LabelStyle::Primary,
+                    span: *span,
+                    message: format!(
+                        "`{}` spans {:#x}..{:#x}, but it was given with --deny-bank-crossing",
+                        label, start, end,
+                    ),
+                }],
+            }),
+            Error::LabelNamesCollide { name, label, span, previous_label, previous_span } => r.write(Diagnostic {
+                message: format!("labels `{}` and `{}` would both be written as `{}`", previous_label, label, name),
+                labels: vec![Label {
+                    style: LabelStyle::Primary,
+                    span: *span,
+                    message: format!("`{}` defined here", label),
+                }, Label {
+                    style: LabelStyle::Secondary,
+                    span: *previous_span,
+                    message: format!("`{}` defined here", previous_label),
+                }],
+            }),
         }
     }
 }
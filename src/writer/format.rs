@@ -0,0 +1,146 @@
+use std::path::Path;
+use super::{Error, Symbol, YotType, intel_hex, s_record, source_array};
+
+/// Everything an output format gets to encode.
+pub struct Image<'a> {
+    pub binary: &'a [u8],
+    pub symbols: &'a [Symbol],
+    pub entry_point: usize,
+    pub yot_type: YotType,
+}
+
+/// An output format the assembled image can be written in.
+pub trait Format {
+    /// The name the format is selected by, e.g. with `--format`.
+    fn name(&self) -> &str;
+
+    /// Whether the output is binary rather than text. Binary output is not
+    /// written to a terminal unless it is forced.
+    fn is_binary(&self) -> bool {
+        false
+    }
+
+    /// Encodes the image to be written to the given path, which is `-` for
+    /// the standard output.
+    fn encode(&self, image: &Image, path: &Path) -> Result<Vec<u8>, Error>;
+}
+
+/// The output formats available by name. The raw format is always
+/// registered and is the default.
+pub struct Formats {
+    formats: Vec<Box<dyn Format>>,
+}
+
+pub const DEFAULT_FORMAT: &str = "raw";
+
+impl Formats {
+    pub fn new() -> Formats {
+        Formats { formats: vec![Box::new(Raw)] }
+    }
+
+    /// Registers a format, replacing any format registered under the same name.
+    pub fn register(&mut self, format: Box<dyn Format>) {
+        self.formats.retain(|registered| registered.name() != format.name());
+        self.formats.push(format);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn Format> {
+        self.formats.iter().find(|format| format.name() == name).map(|format| format.as_ref())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        self.formats.iter().map(|format| format.name().to_owned()).collect()
+    }
+}
+
+impl Default for Formats {
+    fn default() -> Formats {
+        Formats::new()
+    }
+}
+
+fn array_name(path: &Path, array_name: &Option<String>) -> String {
+    match array_name {
+        Some(array_name) => array_name.to_owned(),
+        None => path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default(),
+    }
+}
+
+/// The bare binary.
+pub struct Raw;
+
+impl Format for Raw {
+    fn name(&self) -> &str {
+        DEFAULT_FORMAT
+    }
+
+    fn is_binary(&self) -> bool {
+        true
+    }
+
+    fn encode(&self, image: &Image, _: &Path) -> Result<Vec<u8>, Error> {
+        Ok(image.binary.to_vec())
+    }
+}
+
+pub struct IntelHex {
+    pub record_length: usize,
+}
+
+impl Format for IntelHex {
+    fn name(&self) -> &str {
+        "ihex"
+    }
+
+    fn encode(&self, image: &Image, _: &Path) -> Result<Vec<u8>, Error> {
+        Ok(intel_hex::encode(image.binary, image.yot_type, image.entry_point, self.record_length).into_bytes())
+    }
+}
+
+/// Motorola S-records, with the output file stem as the header name.
+pub struct SRecord {
+    pub record_length: usize,
+}
+
+impl Format for SRecord {
+    fn name(&self) -> &str {
+        "srec"
+    }
+
+    fn encode(&self, image: &Image, path: &Path) -> Result<Vec<u8>, Error> {
+        let name = array_name(path, &None);
+        Ok(s_record::encode(image.binary, image.yot_type, image.entry_point, self.record_length, &name).into_bytes())
+    }
+}
+
+/// A C header with the binary as an array, named after the output file
+/// stem unless given a name.
+pub struct CArray {
+    pub array_name: Option<String>,
+}
+
+impl Format for CArray {
+    fn name(&self) -> &str {
+        "c"
+    }
+
+    fn encode(&self, image: &Image, path: &Path) -> Result<Vec<u8>, Error> {
+        source_array::encode_c(image.binary, image.symbols, image.yot_type, &array_name(path, &self.array_name)).map(String::into_bytes)
+    }
+}
+
+/// A Rust source file with the binary as an array, named after the output
+/// file stem unless given a name.
+pub struct RustArray {
+    pub array_name: Option<String>,
+}
+
+impl Format for RustArray {
+    fn name(&self) -> &str {
+        "rust"
+    }
+
+    fn encode(&self, image: &Image, path: &Path) -> Result<Vec<u8>, Error> {
+        source_array::encode_rust(image.binary, image.symbols, image.yot_type, &array_name(path, &self.array_name)).map(String::into_bytes)
+    }
+}
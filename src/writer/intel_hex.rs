@@ -0,0 +1,180 @@
+use super::YotType;
+use std::fmt::Write;
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+const START_SEGMENT_ADDRESS: u8 = 0x03;
+const EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const START_LINEAR_ADDRESS: u8 = 0x05;
+
+fn record(output: &mut String, address: u16, record_type: u8, data: &[u8]) {
+    let mut checksum: u8 = (data.len() as u8)
+        .wrapping_add((address >> 8) as u8)
+        .wrapping_add(address as u8)
+        .wrapping_add(record_type);
+    let _ = write!(output, ":{:02X}{:04X}{:02X}", data.len(), address, record_type);
+    for byte in data.iter() {
+        checksum = checksum.wrapping_add(*byte);
+        let _ = write!(output, "{:02X}", byte);
+    }
+    let _ = writeln!(output, "{:02X}", checksum.wrapping_neg());
+}
+
+/// Encodes the binary as Intel HEX with at most `record_length` data bytes
+/// per record. Images of `yot-32` and `yot-64` are addressed with extended
+/// linear address records and start with a start linear address record,
+/// while the 16-bit address space of the smaller Yot types only needs a
+/// start segment address record with a zero code segment, unless the
+/// image itself does not fit in it.
+pub(super) fn encode(binary: &[u8], yot_type: YotType, entry_point: usize, record_length: usize) -> String {
+    let linear = matches!(yot_type, YotType::Y32 | YotType::Y64) || binary.len() > 0x10000;
+    let mut output: String = String::new();
+    let mut upper_address: Option<usize> = None;
+    let mut address: usize = 0;
+
+    while address < binary.len() {
+        if linear && upper_address != Some(address >> 16) {
+            upper_address = Some(address >> 16);
+            record(&mut output, 0, EXTENDED_LINEAR_ADDRESS, &((address >> 16) as u16).to_be_bytes());
+        }
+        let segment_end = (address | 0xffff) + 1;
+        let end = binary.len().min(address + record_length).min(segment_end);
+        record(&mut output, address as u16, DATA, &binary[address..end]);
+        address = end;
+    }
+
+    if linear {
+        record(&mut output, 0, START_LINEAR_ADDRESS, &(entry_point as u32).to_be_bytes());
+    } else {
+        let mut start_address: Vec<u8> = vec![0x00, 0x00];
+        start_address.extend(&(entry_point as u16).to_be_bytes());
+        record(&mut output, 0, START_SEGMENT_ADDRESS, &start_address);
+    }
+    record(&mut output, 0, END_OF_FILE, &[]);
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::format::{Format, Image, IntelHex, Raw};
+    use std::path::Path;
+
+    struct Record {
+        address: u16,
+        record_type: u8,
+        data: Vec<u8>,
+    }
+
+    /// Parses the records, checking the byte count and checksum of each.
+    fn parse(output: &str) -> Vec<Record> {
+        output.lines().map(|line| {
+            assert!(line.starts_with(':'), "{}", line);
+            let bytes: Vec<u8> = (1..line.len()).step_by(2)
+                .map(|i| u8::from_str_radix(&line[i..i + 2], 16).unwrap())
+                .collect();
+            assert_eq!(bytes.len(), bytes[0] as usize + 5, "{}", line);
+            assert_eq!(bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)), 0, "{}", line);
+            Record {
+                address: u16::from_be_bytes([bytes[1], bytes[2]]),
+                record_type: bytes[3],
+                data: bytes[4..bytes.len() - 1].to_vec(),
+            }
+        }).collect()
+    }
+
+    /// Writes the data records back into a binary.
+    fn decode(records: &[Record]) -> Vec<u8> {
+        let mut binary: Vec<u8> = Vec::new();
+        let mut upper_address: usize = 0;
+        for record in records.iter() {
+            match record.record_type {
+                EXTENDED_LINEAR_ADDRESS => upper_address = u16::from_be_bytes([record.data[0], record.data[1]]) as usize,
+                DATA => {
+                    let address = (upper_address << 16) | record.address as usize;
+                    assert_eq!(address, binary.len());
+                    binary.extend(&record.data);
+                }
+                _ => (),
+            }
+        }
+        binary
+    }
+
+    fn image(binary: &[u8], yot_type: YotType) -> Image<'_> {
+        Image { binary, symbols: &[], entry_point: yot_type as usize * 2, yot_type }
+    }
+
+    fn encode_image(image: &Image, record_length: usize) -> Vec<Record> {
+        let output = IntelHex { record_length }.encode(image, Path::new("out.hex"));
+        parse(&String::from_utf8(output.unwrap()).unwrap())
+    }
+
+    fn binary(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i * 7 + i / 256) as u8).collect()
+    }
+
+    #[test]
+    fn decodes_to_the_raw_output() {
+        for yot_type in [YotType::Y8, YotType::Y16, YotType::Y32, YotType::Y64] {
+            for record_length in [1, 16, 255] {
+                let binary = binary(1000);
+                let image = image(&binary, yot_type);
+                let records = encode_image(&image, record_length);
+                assert_eq!(decode(&records), Raw.encode(&image, Path::new("out.bin")).unwrap());
+                let mut data_records = records.iter().filter(|record| record.record_type == DATA);
+                assert!(data_records.all(|record| record.data.len() <= record_length));
+            }
+        }
+    }
+
+    #[test]
+    fn extends_the_address_past_0xffff() {
+        let binary = binary(0x10000 + 0x40);
+        let image = image(&binary, YotType::Y16);
+        let records = encode_image(&image, 0x30);
+        assert_eq!(decode(&records), binary);
+
+        let extended: Vec<usize> = records.iter().enumerate()
+            .filter(|(_, record)| record.record_type == EXTENDED_LINEAR_ADDRESS)
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(extended.len(), 2);
+        assert_eq!(extended[0], 0);
+        assert_eq!(records[extended[0]].data, [0x00, 0x00]);
+        assert_eq!(records[extended[1]].data, [0x00, 0x01]);
+        // no record crosses into the next 64 KiB, and the next one starts
+        // right after the extended address record
+        let last_low = &records[extended[1] - 1];
+        assert_eq!(last_low.address as usize + last_low.data.len(), 0x10000);
+        assert_eq!(records[extended[1] + 1].address, 0);
+    }
+
+    #[test]
+    fn addresses_small_images_without_extended_records() {
+        let binary = binary(0x200);
+        let records = encode_image(&image(&binary, YotType::Y16), 16);
+        assert!(records.iter().all(|record| record.record_type != EXTENDED_LINEAR_ADDRESS));
+        assert!(records.iter().all(|record| record.record_type != START_LINEAR_ADDRESS));
+        let start = records.iter().find(|record| record.record_type == START_SEGMENT_ADDRESS).unwrap();
+        assert_eq!(start.data, [0x00, 0x00, 0x00, 0x04]);
+    }
+
+    #[test]
+    fn ends_with_the_start_and_end_of_file_records() {
+        for yot_type in [YotType::Y32, YotType::Y64] {
+            let binary = binary(0x20);
+            let records = encode_image(&image(&binary, yot_type), 16);
+            let start = &records[records.len() - 2];
+            assert_eq!(start.record_type, START_LINEAR_ADDRESS);
+            assert_eq!(start.address, 0);
+            assert_eq!(start.data, (yot_type as u32 * 2).to_be_bytes());
+            assert_eq!(records.iter().filter(|record| record.record_type == START_LINEAR_ADDRESS).count(), 1);
+        }
+
+        let output = encode(&binary(0x20), YotType::Y32, 8, 16);
+        assert!(output.ends_with(":00000001FF\n"));
+        assert_eq!(output.matches(":00000001FF").count(), 1);
+    }
+}
@@ -1,14 +1,193 @@
 pub use error::Error;
+pub use format::{CArray, DEFAULT_FORMAT, Format, Formats, Image, IntelHex, Raw, RustArray, SRecord};
 use std::fs;
-use std::path::Path;
-use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::io::{self, IsTerminal, Write};
+use std::process;
+use super::{Symbol, YotType};
 
+mod bank;
 mod error;
+mod format;
+mod intel_hex;
+mod s_record;
+mod source_array;
 
-pub(super) fn write(path: &Path, binary: &[u8]) -> Result<(), Error> {
-    let mut file = fs::File::create(path)
-        .map_err(|io_error| Error::CouldNotWriteFile { file_path: path.to_path_buf(), io_error })?;
-    file.write_all(binary)
+const STANDARD_OUTPUT_NAME: &str = "<stdout>";
+
+fn is_standard_output(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Creates a new file next to the given path to write its contents to
+/// before moving it into place.
+fn create_temporary_file(path: &Path) -> io::Result<(PathBuf, fs::File)> {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let file_name = path.file_name().map(|file_name| file_name.to_string_lossy().to_string()).unwrap_or_default();
+    let mut attempt: usize = 0;
+    loop {
+        let temporary_path = directory.join(format!(".{}.{}.{}.tmp", file_name, process::id(), attempt));
+        match fs::OpenOptions::new().write(true).create_new(true).open(&temporary_path) {
+            Ok(file) => return Ok((temporary_path, file)),
+            Err(io_error) if io_error.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+            Err(io_error) => return Err(io_error),
+        }
+    }
+}
+
+fn replace_file(path: &Path, temporary_path: &Path, mut file: fs::File, contents: &[u8]) -> io::Result<()> {
+    file.write_all(contents)?;
+    if let Ok(metadata) = fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.sync_all()?;
+    drop(file);
+    fs::rename(temporary_path, path)
+}
+
+/// Writes the contents to the file at the given path, or to the standard
+/// output if the path is `-`.
+///
+/// The contents are written to a temporary file in the same directory first,
+/// which then replaces the file at the given path, so a failure never leaves
+/// a truncated file behind and keeps the previous one intact. A file that
+/// already has the same contents is left untouched, keeping its
+/// modification time.
+fn write_file(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    if is_standard_output(path) {
+        let mut stdout = io::stdout().lock();
+        return stdout.write_all(contents)
+            .and_then(|()| stdout.flush())
+            .map_err(|io_error| Error::CouldNotWriteFile { file_path: PathBuf::from(STANDARD_OUTPUT_NAME), io_error });
+    }
+    if matches!(fs::read(path), Ok(previous_contents) if previous_contents == contents) {
+        return Ok(());
+    }
+    let (temporary_path, file) = create_temporary_file(path)
         .map_err(|io_error| Error::CouldNotWriteFile { file_path: path.to_path_buf(), io_error })?;
+    replace_file(path, &temporary_path, file, contents).map_err(|io_error| {
+        let _ = fs::remove_file(&temporary_path);
+        Error::CouldNotWriteFile { file_path: path.to_path_buf(), io_error }
+    })
+}
+
+/// Refuses binary output to the standard output if it is a terminal, unless
+/// it is forced. Whether it is one is only checked when it matters.
+fn check_terminal(path: &Path, format: &dyn Format, force: bool, is_terminal: impl FnOnce() -> bool) -> Result<(), Error> {
+    if format.is_binary() && is_standard_output(path) && !force && is_terminal() {
+        return Err(Error::BinaryOutputToTerminal);
+    }
     Ok(())
 }
+
+/// Writes the image in the given format. Binary output is refused when the
+/// standard output is a terminal, unless it is forced.
+pub fn write(path: &Path, image: &Image, format: &dyn Format, force: bool) -> Result<(), Error> {
+    check_terminal(path, format, force, || io::stdout().is_terminal())?;
+    write_file(path, &format.encode(image, path)?)
+}
+
+/// The layout of a binary split into fixed-size banks, one file per bank.
+pub struct Banks<'a> {
+    pub size: usize,
+    pub fill_byte: u8,
+    pub name_template: &'a str,
+    pub pinned_labels: &'a [String],
+}
+
+/// Writes the raw binary as separate bank files, named after the output path
+/// by the bank name template. Nothing is written if any label crosses a bank
+/// boundary without being allowed to.
+pub fn write_banks(path: &Path, binary: &[u8], symbols: &[Symbol], banks: &Banks) -> Result<(), Vec<Error>> {
+    if is_standard_output(path) {
+        return Err(vec![Error::BanksToStandardOutput]);
+    }
+    bank::check_crossings(symbols, banks.size, banks.pinned_labels)?;
+    for (index, contents) in bank::split(binary, banks.size, banks.fill_byte).iter().enumerate() {
+        write_file(&bank::path(path, banks.name_template, index), contents).map_err(|err| vec![err])?;
+    }
+    Ok(())
+}
+
+pub fn write_text(path: &Path, text: &str) -> Result<(), Error> {
+    write_file(path, text.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    /// A fresh directory for the files of a single test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ysm-writer-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut file_names: Vec<String> = fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        file_names.sort();
+        file_names
+    }
+
+    #[test]
+    fn replaces_files_without_leaving_temporary_files_behind() {
+        let dir = temp_dir("replace");
+        let path = dir.join("out.bin");
+        write_file(&path, b"first").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"first");
+        write_file(&path, b"second, longer").unwrap();
+        write_file(&path, b"third").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"third");
+        assert_eq!(file_names(&dir), ["out.bin"]);
+    }
+
+    #[test]
+    fn leaves_files_with_the_same_contents_untouched() {
+        let dir = temp_dir("unchanged");
+        let path = dir.join("out.bin");
+        write_file(&path, b"same").unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+
+        write_file(&path, b"same").unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+        write_file(&path, b"different").unwrap();
+        assert_ne!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+    }
+
+    #[test]
+    fn cleans_up_after_failing_to_replace_a_file() {
+        let dir = temp_dir("failure");
+        // a file cannot replace a directory
+        let path = dir.join("out.bin");
+        fs::create_dir(&path).unwrap();
+        match write_file(&path, b"contents") {
+            Err(Error::CouldNotWriteFile { file_path, .. }) => assert_eq!(file_path, path),
+            result => panic!("{:?}", result),
+        }
+        assert_eq!(file_names(&dir), ["out.bin"]);
+        assert!(path.is_dir());
+
+        let path = dir.join("missing").join("out.bin");
+        assert!(matches!(write_file(&path, b"contents"), Err(Error::CouldNotWriteFile { file_path, .. }) if file_path == path));
+    }
+
+    #[test]
+    fn refuses_binary_output_to_a_terminal_unless_forced() {
+        let standard_output = Path::new("-");
+        let terminal = || true;
+        assert!(matches!(check_terminal(standard_output, &Raw, false, terminal), Err(Error::BinaryOutputToTerminal)));
+        assert!(check_terminal(standard_output, &Raw, true, terminal).is_ok());
+        assert!(check_terminal(standard_output, &Raw, false, || false).is_ok());
+        assert!(check_terminal(standard_output, &IntelHex { record_length: 16 }, false, terminal).is_ok());
+        assert!(check_terminal(Path::new("out.bin"), &Raw, false, || panic!("checked a file")).is_ok());
+    }
+}
@@ -0,0 +1,168 @@
+use super::YotType;
+use std::fmt::Write;
+
+fn record(output: &mut String, record_type: u8, address: usize, address_width: usize, data: &[u8]) {
+    let count = address_width + data.len() + 1;
+    let mut checksum: u8 = count as u8;
+    let _ = write!(output, "S{}{:02X}", record_type, count);
+    for i in (0..address_width).rev() {
+        let byte = (address >> (i * 8)) as u8;
+        checksum = checksum.wrapping_add(byte);
+        let _ = write!(output, "{:02X}", byte);
+    }
+    for byte in data.iter() {
+        checksum = checksum.wrapping_add(*byte);
+        let _ = write!(output, "{:02X}", byte);
+    }
+    let _ = writeln!(output, "{:02X}", !checksum);
+}
+
+/// Picks the narrowest of the S19, S28 and S37 variants able to address
+/// both the whole image and the entry point, but never narrower than the
+/// addresses of the Yot type.
+fn address_width(binary: &[u8], yot_type: YotType, entry_point: usize) -> usize {
+    let highest_address = binary.len().saturating_sub(1).max(entry_point);
+    let required_width = match highest_address {
+        0..=0xffff => 2,
+        0x10000..=0xffffff => 3,
+        _ => 4,
+    };
+    required_width.max((yot_type as usize).clamp(2, 4))
+}
+
+/// Encodes the binary as Motorola S-records with at most `record_length`
+/// data bytes per record, framed by a header record carrying `name` and by
+/// a record count and a termination record holding the entry point. Names
+/// too long for the byte count of the header record are truncated.
+pub(super) fn encode(binary: &[u8], yot_type: YotType, entry_point: usize, record_length: usize, name: &str) -> String {
+    let address_width = address_width(binary, yot_type, entry_point);
+    let record_length = record_length.min(0xff - address_width - 1);
+    let (data_record_type, termination_record_type) = match address_width {
+        2 => (1, 9),
+        3 => (2, 8),
+        _ => (3, 7),
+    };
+    let mut output: String = String::new();
+
+    let name = name.as_bytes();
+    record(&mut output, 0, 0, 2, &name[..name.len().min(0xff - 2 - 1)]);
+
+    let chunks = binary.chunks(record_length);
+    let record_count = chunks.len();
+    for (i, chunk) in chunks.enumerate() {
+        record(&mut output, data_record_type, i * record_length, address_width, chunk);
+    }
+
+    if record_count <= 0xffff {
+        record(&mut output, 5, record_count, 2, &[]);
+    } else {
+        record(&mut output, 6, record_count, 3, &[]);
+    }
+    record(&mut output, termination_record_type, entry_point, address_width, &[]);
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Record {
+        record_type: u8,
+        address: usize,
+        data: Vec<u8>,
+    }
+
+    fn address_width_of(record_type: u8) -> usize {
+        match record_type {
+            0 | 1 | 5 | 9 => 2,
+            2 | 6 | 8 => 3,
+            _ => 4,
+        }
+    }
+
+    /// Parses the records, checking the byte count and checksum of each.
+    fn parse(output: &str) -> Vec<Record> {
+        output.lines().map(|line| {
+            assert!(line.starts_with('S'), "{}", line);
+            let record_type = line[1..2].parse::<u8>().unwrap();
+            let bytes: Vec<u8> = (2..line.len()).step_by(2)
+                .map(|i| u8::from_str_radix(&line[i..i + 2], 16).unwrap())
+                .collect();
+            assert_eq!(bytes[0] as usize, bytes.len() - 1, "{}", line);
+            assert_eq!(bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)), 0xff, "{}", line);
+            let address_width = address_width_of(record_type);
+            Record {
+                record_type,
+                address: bytes[1..1 + address_width].iter().fold(0, |address, byte| (address << 8) | *byte as usize),
+                data: bytes[1 + address_width..bytes.len() - 1].to_vec(),
+            }
+        }).collect()
+    }
+
+    fn binary(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i * 13 + i / 256) as u8).collect()
+    }
+
+    fn decode(records: &[Record]) -> Vec<u8> {
+        let mut binary: Vec<u8> = Vec::new();
+        for record in records.iter().filter(|record| (1..=3).contains(&record.record_type)) {
+            assert_eq!(record.address, binary.len());
+            binary.extend(&record.data);
+        }
+        binary
+    }
+
+    #[test]
+    fn picks_the_record_types_by_address_width() {
+        let cases = [
+            (YotType::Y8, 0x100, 1, 9),
+            (YotType::Y16, 0x100, 1, 9),
+            (YotType::Y16, 0x10010, 2, 8),
+            (YotType::Y32, 0x100, 3, 7),
+            (YotType::Y64, 0x100, 3, 7),
+        ];
+        for (yot_type, size, data_record_type, termination_record_type) in cases {
+            let binary = binary(size);
+            let records = parse(&encode(&binary, yot_type, yot_type as usize * 2, 32, "out"));
+            assert_eq!(decode(&records), binary);
+            let data_records = &records[1..records.len() - 2];
+            assert!(data_records.iter().all(|record| record.record_type == data_record_type));
+            assert!(data_records.iter().all(|record| record.data.len() <= 32));
+            let termination = records.last().unwrap();
+            assert_eq!(termination.record_type, termination_record_type);
+            assert_eq!(termination.address, yot_type as usize * 2);
+            assert!(termination.data.is_empty());
+        }
+    }
+
+    #[test]
+    fn starts_with_a_header_carrying_the_name() {
+        let records = parse(&encode(&binary(0x10), YotType::Y16, 4, 32, "mul"));
+        assert_eq!(records[0].record_type, 0);
+        assert_eq!(records[0].address, 0);
+        assert_eq!(records[0].data, b"mul");
+    }
+
+    #[test]
+    fn truncates_long_names() {
+        let name = "x".repeat(300);
+        let records = parse(&encode(&binary(0x10), YotType::Y16, 4, 32, &name));
+        assert_eq!(records[0].data, name.as_bytes()[..252]);
+    }
+
+    #[test]
+    fn counts_the_data_records() {
+        let records = parse(&encode(&binary(100), YotType::Y16, 4, 16, "out"));
+        let count = &records[records.len() - 2];
+        assert_eq!(count.record_type, 5);
+        assert_eq!(count.address, 7);
+        assert!(count.data.is_empty());
+
+        let records = parse(&encode(&binary(0x10001), YotType::Y16, 4, 1, "out"));
+        let count = &records[records.len() - 2];
+        assert_eq!(count.record_type, 6);
+        assert_eq!(count.address, 0x10001);
+        assert_eq!(records.iter().filter(|record| record.record_type == 5).count(), 0);
+    }
+}
@@ -0,0 +1,170 @@
+use super::{Error, Symbol, YotType};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+const BYTES_PER_LINE: usize = 12;
+
+/// Turns an arbitrary name into an identifier valid in both C and Rust,
+/// keeping it as it is if it already is one.
+fn identifier(name: &str) -> String {
+    let mut identifier: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' })
+        .collect();
+    if !matches!(identifier.chars().next(), Some(c) if c.is_ascii_alphabetic() || c == '_') {
+        identifier.insert(0, '_');
+    }
+    identifier
+}
+
+/// Turns an arbitrary name into an upper snake case identifier valid in
+/// both C and Rust.
+fn constant_name(name: &str) -> String {
+    identifier(name).to_ascii_uppercase()
+}
+
+fn write_bytes(output: &mut String, binary: &[u8]) {
+    for line in binary.chunks(BYTES_PER_LINE) {
+        let bytes: Vec<String> = line.iter().map(|byte| format!("0x{:02x},", byte)).collect();
+        let _ = writeln!(output, "    {}", bytes.join(" "));
+    }
+}
+
+fn address(address: usize, yot_type: YotType) -> String {
+    format!("0x{:0width$x}", address, width = yot_type as usize * 2)
+}
+
+/// Names the constant holding the address of every label
+/// `<prefix>_LABEL_<label>`, apart from anything else named after the
+/// prefix, and makes sure no two labels end up with the same name.
+fn label_constants<'a>(prefix: &str, symbols: &'a [Symbol]) -> Result<Vec<(String, &'a Symbol)>, Error> {
+    let mut named: HashMap<String, &Symbol> = HashMap::new();
+    let mut constants: Vec<(String, &Symbol)> = Vec::with_capacity(symbols.len());
+    for symbol in symbols.iter() {
+        let name = format!("{}_LABEL_{}", prefix, constant_name(&symbol.label));
+        if let Some(previous) = named.insert(name.clone(), symbol) {
+            return Err(Error::LabelNamesCollide {
+                name,
+                label: symbol.label.clone(),
+                span: symbol.span,
+                previous_label: previous.label.clone(),
+                previous_span: previous.span,
+            });
+        }
+        constants.push((name, symbol));
+    }
+    Ok(constants)
+}
+
+/// Encodes the binary as a C header defining a `uint8_t` array called
+/// `name`, a `<NAME>_LEN` macro with its length and a `<NAME>_LABEL_<LABEL>`
+/// macro with the address of every label, `<NAME>` being `name` in upper
+/// case.
+pub(super) fn encode_c(binary: &[u8], symbols: &[Symbol], yot_type: YotType, name: &str) -> Result<String, Error> {
+    let prefix = constant_name(name);
+    let label_constants = label_constants(&prefix, symbols)?;
+    let mut output: String = String::new();
+
+    let _ = writeln!(output, "/* Generated by ysm */");
+    let _ = writeln!(output);
+    let _ = writeln!(output, "#ifndef {}_H", prefix);
+    let _ = writeln!(output, "#define {}_H", prefix);
+    let _ = writeln!(output);
+    let _ = writeln!(output, "#include <stdint.h>");
+    let _ = writeln!(output);
+    let _ = writeln!(output, "#define {}_LEN {}", prefix, binary.len());
+    for (constant, symbol) in label_constants.iter() {
+        let _ = writeln!(output, "#define {} {}", constant, address(symbol.address, yot_type));
+    }
+    let _ = writeln!(output);
+    let _ = writeln!(output, "static const uint8_t {}[{}_LEN] = {{", identifier(name), prefix);
+    write_bytes(&mut output, binary);
+    let _ = writeln!(output, "}};");
+    let _ = writeln!(output);
+    let _ = writeln!(output, "#endif");
+
+    Ok(output)
+}
+
+/// Encodes the binary as a Rust source file defining a static byte array
+/// called `name` and a `<NAME>_LABEL_<LABEL>` constant with the address of
+/// every label, typed after the address width of the Yot type.
+pub(super) fn encode_rust(binary: &[u8], symbols: &[Symbol], yot_type: YotType, name: &str) -> Result<String, Error> {
+    let prefix = constant_name(name);
+    let label_constants = label_constants(&prefix, symbols)?;
+    let address_type = match yot_type {
+        YotType::Y8 => "u8",
+        YotType::Y16 => "u16",
+        YotType::Y32 => "u32",
+        YotType::Y64 => "u64",
+    };
+    let mut output: String = String::new();
+
+    let _ = writeln!(output, "// Generated by ysm");
+    let _ = writeln!(output);
+    for (constant, symbol) in label_constants.iter() {
+        let _ = writeln!(output, "pub const {}: {} = {};", constant, address_type, address(symbol.address, yot_type));
+    }
+    if !symbols.is_empty() {
+        let _ = writeln!(output);
+    }
+    let _ = writeln!(output, "pub static {}: [u8; {}] = [", prefix, binary.len());
+    write_bytes(&mut output, binary);
+    let _ = writeln!(output, "];");
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::span::{Location, Span};
+
+    #[test]
+    fn names_the_c_array_as_given() {
+        let header = encode_c(&[0x01, 0x02], &[], YotType::Y8, "fooBar").unwrap();
+        assert!(header.contains("#define FOOBAR_LEN 2\n"));
+        assert!(header.contains("static const uint8_t fooBar[FOOBAR_LEN] = {\n"));
+    }
+
+    fn symbol(label: &str, address: usize) -> Symbol {
+        let span = Span { file_id: 0, from: Location { offset: address }, to: Location { offset: address + 1 } };
+        Symbol { label: label.to_owned(), address, size: 1, span }
+    }
+
+    #[test]
+    fn keeps_label_macros_apart_from_the_length() {
+        let symbols = [symbol("len", 0x02), symbol("mul", 0x05)];
+        let header = encode_c(&[0x00; 8], &symbols, YotType::Y8, "prog").unwrap();
+        assert!(header.contains("#define PROG_LEN 8\n"));
+        assert!(header.contains("#define PROG_LABEL_LEN 0x02\n"));
+        assert!(header.contains("#define PROG_LABEL_MUL 0x05\n"));
+
+        let source = encode_rust(&[0x00; 8], &symbols, YotType::Y16, "prog").unwrap();
+        assert!(source.contains("pub const PROG_LABEL_LEN: u16 = 0x0002;\n"));
+        assert!(source.contains("pub static PROG: [u8; 8] = [\n"));
+    }
+
+    #[test]
+    fn rejects_labels_that_end_up_with_the_same_name() {
+        for (a, b) in [("loop", "LOOP"), ("my-loop", "my.loop"), ("my-loop", "my_loop")] {
+            let symbols = [symbol(a, 0x02), symbol("mul", 0x04), symbol(b, 0x06)];
+            match encode_c(&[0x00; 8], &symbols, YotType::Y8, "prog") {
+                Err(Error::LabelNamesCollide { label, previous_label, .. }) => {
+                    assert_eq!((previous_label.as_str(), label.as_str()), (a, b));
+                }
+                result => panic!("{} and {}: {:?}", a, b, result),
+            }
+            assert!(encode_rust(&[0x00; 8], &symbols, YotType::Y8, "prog").is_err());
+        }
+    }
+
+    #[test]
+    fn turns_names_into_identifiers() {
+        assert_eq!(identifier("mul"), "mul");
+        assert_eq!(identifier("Boot_ROM"), "Boot_ROM");
+        assert_eq!(identifier("my-prog.v2"), "my_prog_v2");
+        assert_eq!(identifier("8bit"), "_8bit");
+        assert_eq!(constant_name("my-prog"), "MY_PROG");
+    }
+}
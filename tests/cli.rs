@@ -0,0 +1,98 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn ysm(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ysm")).args(args).output().unwrap()
+}
+
+/// Runs ysm with the input piped to its standard input.
+fn ysm_with_input(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ysm"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// A fresh directory for the files of a single test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ysm-cli-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn assemble_exits_with_failure_on_errors() {
+    let dir = temp_dir("assemble-errors");
+    let source = dir.join("in.ysm");
+    let output = dir.join("out.bin");
+    fs::write(&source, "#01 #02 add brk\n:mul\nnop nop nop nop nop nop nop nop\njmp\n").unwrap();
+
+    let missing = dir.join("missing.ysm");
+    for args in [
+        vec!["assemble", "yot-8", missing.to_str().unwrap(), output.to_str().unwrap(), "--sp", "128"],
+        vec!["yot-8", missing.to_str().unwrap(), output.to_str().unwrap(), "--sp", "128"],
+        vec!["assemble", "yot-8", source.to_str().unwrap(), output.to_str().unwrap(), "--sp", "128", "-f", "bogus"],
+        vec![
+            "assemble", "yot-8", source.to_str().unwrap(), output.to_str().unwrap(), "--sp", "128",
+            "--bank-size", "8", "--deny-bank-crossing", "mul",
+        ],
+    ] {
+        let result = ysm(&args);
+        assert!(!result.status.success(), "{:?}", args);
+        assert!(!result.stderr.is_empty(), "{:?}", args);
+    }
+
+    let result = ysm(&["assemble", "yot-8", source.to_str().unwrap(), output.to_str().unwrap(), "--sp", "128"]);
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    assert!(output.exists());
+}
+
+#[test]
+fn disassemble_warns_about_binaries_it_cannot_reproduce() {
+    let dir = temp_dir("disassemble-warnings");
+    let output = dir.join("out.ysm");
+    // no header, and a byte that isn't an opcode
+    let binary = dir.join("in.bin");
+    fs::write(&binary, [0x20, 0x01, 0xff]).unwrap();
+
+    let result = ysm(&["disassemble", "yot-8", binary.to_str().unwrap(), output.to_str().unwrap()]);
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    assert!(String::from_utf8_lossy(&result.stderr).contains("warning"));
+    assert!(fs::read_to_string(&output).unwrap().contains("unknown byte 0xff"));
+}
+
+#[test]
+fn reads_the_standard_input_and_writes_the_standard_output() {
+    let dir = temp_dir("standard-streams");
+    let source = "#01 #02 add brk\n";
+    let source_path = dir.join("in.ysm");
+    let binary_path = dir.join("out.bin");
+    fs::write(&source_path, source).unwrap();
+    let result = ysm(&["assemble", "yot-8", source_path.to_str().unwrap(), binary_path.to_str().unwrap(), "--sp", "128"]);
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    let binary = fs::read(&binary_path).unwrap();
+
+    // standard output isn't a terminal here, so binary output isn't refused
+    let result = ysm_with_input(&["assemble", "yot-8", "-", "-", "--sp", "128"], source.as_bytes());
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    assert_eq!(result.stdout, binary);
+
+    let result = ysm_with_input(&["disassemble", "yot-8", "-", "-"], &binary);
+    assert!(result.status.success(), "{}", String::from_utf8_lossy(&result.stderr));
+    let disassembly = String::from_utf8(result.stdout).unwrap();
+    assert!(disassembly.contains("add"), "{}", disassembly);
+
+    // errors in the standard input are reported under its name
+    let result = ysm_with_input(&["assemble", "yot-8", "-", "-", "--sp", "128"], b"#01 missing\n");
+    assert!(!result.status.success());
+    assert!(result.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&result.stderr).contains("<stdin>"));
+}